@@ -0,0 +1,88 @@
+#![allow(non_snake_case)]
+
+use std::fs;
+
+use Blockchain_in_Rust::{Interface::CLI, block::Block, miner::Miner, coin_selection::{Fee, Strategy}, config::{ChainConfig, Network}, error::Result, transactions::Transaction};
+
+#[allow(dead_code)]
+fn print_database_contents(file_db: &str) {  
+    let db = sled::open(file_db).expect("Failed to open the database"); 
+    // 遍历数据库中的所有键值对  
+    for result in db.iter() {  
+        match result {  
+            Ok((key, value)) => {  
+                // 打印键  
+                println!("Key: {:?}", key);  
+                
+                // 调整后的反序列化代码  
+                if let Ok(block) = bincode::deserialize::<Block>(&value) {  
+                    println!("Value: {:?}", block);  
+                } else {  
+                    println!("Failed to deserialize value");  
+                }  
+            }  
+            Err(err) => {  
+                println!("Error reading from database: {:?}", err);  
+            }  
+        }  
+    }  
+    println!("Database contents printed.");
+}
+// 在临时目录下创建 regtest 区块链, 演示挖矿, 转账和链的校验  
+fn main() -> Result<()> {  
+    
+    let mut cli = CLI::new();  
+    // 每次运行都从新的区块链开始  
+    let datadir = std::env::temp_dir().join("blockchain-demo");  
+    if datadir.exists() {  
+        fs::remove_dir_all(&datadir)?;  
+    }  
+    cli.config = ChainConfig::new(Network::Regtest, &datadir);  
+    fs::create_dir_all(cli.config.chain_dir())?;  
+    cli.create_wallets()?;  
+    let wallets = cli.wallets.as_mut().expect("Wallets not found"); // Get a mutable reference  
+    println!("address1: \n");
+    let address1 = wallets.new_wallet()?;  
+    println!("address2: \n");
+    let address2 = wallets.new_wallet()?;  
+    println!("address3: \n");
+    let address3 = wallets.new_wallet()?;  
+
+    cli.create_blockchain(&address1)?;  
+    
+    cli.get_balance(&address1)?; 
+    //address1 77 
+
+    // coinbase 奖励需要成熟后才能花费, 先挖几个只有 coinbase 的区块
+    let bc = cli.blockchain.as_mut().expect("Blockchain not found");
+    for _ in 0..bc.config.coinbase_maturity {
+        let cb_tx = Transaction::new_coinbase_transcation(&address1, "Reward", bc.height()? + 1, 0, &bc.config)?;
+        bc.MineBlock(vec![cb_tx], &Miner::default())?;
+    }
+    //address1 217
+
+    cli.send(&address1, &address2, 66, Fee::default(), Strategy::BranchAndBound)?; 
+    cli.mine(&address1, &Miner::default())?;
+
+    cli.get_balance(&address1)?;  
+    // address1 221
+    cli.get_balance(&address2)?;  
+    // address2 66
+
+    cli.send(&address2, &address3, 50, Fee::default(), Strategy::LargestFirst)?;
+    cli.mine(&address2, &Miner::default())?;
+    // address2 16
+    cli.get_balance(&address3)?;
+    //address3 50
+    cli.print_chain()?;
+    cli.verify_chain()?;
+    cli.get_supply()?;
+
+    // cli.print_chain(DB_FILE);
+
+    // print_database_contents(DB_FILE); 
+    Ok(())
+}
+
+
+
@@ -1,13 +1,34 @@
 #!/bin/bash  
-echo "Adding block: Send 1 BTC to Ivan"  
-cargo run -- addblock -data "Send 1 BTC to Ivan"  
-echo "Mining the block containing \"Send 1 BTC to Ivan\""  
+set -e
 
-# 添加第二个区块  
-echo "Adding block: Pay 0.31337 BTC for a coffee"  
-cargo run -- addblock -data "Pay 0.31337 BTC for a coffee"  
-echo "Mining the block containing \"Pay 0.31337 BTC for a coffee\""  
+DATADIR=${DATADIR:-./data}
+BC="cargo run -q -- --datadir $DATADIR"
+
+# 创建两个钱包地址  
+echo "Creating wallets"  
+$BC createwallet  
+$BC createwallet  
+FROM=$($BC listaddresses | sed -n 1p)  
+TO=$($BC listaddresses | sed -n 2p)  
+
+# 创世区块的奖励发给第一个地址  
+echo "Creating blockchain for $FROM"  
+$BC createblockchain "$FROM"  
+$BC getbalance "$FROM"  
+
+# coinbase 奖励成熟后才能花费, 先挖几个区块  
+echo "Mining blocks until the genesis reward matures"  
+for i in 1 2 3; do  
+    $BC mine "$FROM"  
+done  
+
+# 交易先进入内存池, 再由下一个区块打包  
+echo "Sending 10 from $FROM to $TO"  
+$BC send "$FROM" "$TO" 10 --fee 1  
+$BC listmempool  
+$BC mine "$FROM"  
+$BC getbalance "$TO"  
 
 # 打印整个区块链  
 echo "Printing the blockchain"  
-cargo run -- printchain
\ No newline at end of file
+$BC printchain
@@ -1,132 +1,297 @@
 use crate::block_chain::BlockChain;
-use crate::block::Block;
 use crate::transactions::Transaction;
-use crate::wallet::{self, Wallet, Wallets};
+use crate::wallet::Wallets;
 use crate::UTXOset::UTXOSet;
-use crate::DB_FILE;
-use sled::Db; // 引入 sled 数据库 
 use crate::proof_of_work::ProofOfWork;
-use std::fmt::{self, Debug};  
-use std::env;
-use std::process;  
+use crate::chain_verifier;
+use crate::error::{Error, Result};
+use crate::config::{ChainConfig, Network};
+use crate::coin_selection::{Fee, Strategy};
+use crate::mempool::Mempool;
+use crate::miner::{format_hash_rate, Miner};
+use crate::node::Node;
+use crate::rpc::RpcServer;
+use clap::{Parser, Subcommand};
+use std::fs;
+use std::path::PathBuf;
 
 use crate::functions::{self, validate_address};
+use crate::{GAP_LIMIT, MAX_MEMPOOL_SIZE};
 
 pub struct CLI {  
     pub blockchain: Option<BlockChain>, 
     pub wallets: Option<Wallets>,  
+    // 等待打包的交易, 随区块链一起打开  
+    pub mempool: Option<Mempool>,  
+    // 所在网络的参数以及区块链数据库和钱包文件的位置  
+    pub config: ChainConfig,  
 }  
 
+#[derive(Parser)]  
+#[command(name = "blockchain", about = "A simple blockchain node and wallet")]  
+pub struct Args {  
+    /// Directory holding the blockchain database and wallet file  
+    #[arg(long, global = true, default_value = ".")]  
+    pub datadir: PathBuf,  
+
+    /// Network to use: mainnet, testnet or regtest  
+    #[arg(long, global = true, default_value = "mainnet")]  
+    pub network: Network,  
+
+    /// Passphrase used to unlock an encrypted wallet  
+    #[arg(long, global = true, env = "WALLET_PASSPHRASE", hide_env_values = true)]  
+    pub passphrase: Option<String>,  
+
+    #[command(subcommand)]  
+    pub command: Command,  
+}  
+
+#[derive(Subcommand)]  
+pub enum Command {  
+    /// Generate a new address and save it to the wallet file  
+    Createwallet,  
+    /// List all addresses in the wallet file  
+    Listaddresses,  
+    /// Create an HD seed and print its mnemonic; new addresses are derived from it  
+    Createhdwallet,  
+    /// Print the mnemonic of the HD seed  
+    Exportmnemonic,  
+    /// Restore an HD wallet from its mnemonic and scan the chain for used addresses  
+    Restorewallet {  
+        /// The mnemonic words  
+        #[arg(required = true, num_args = 1..)]  
+        words: Vec<String>,  
+    },  
+    /// Encrypt the private keys in the wallet file under --passphrase (prompted if not given)  
+    Encryptwallet,  
+    /// Re-encrypt the wallet file: the old passphrase is --passphrase, the new one --new-passphrase (prompted if not given)  
+    Changepassphrase {  
+        /// New passphrase for the wallet  
+        #[arg(long, env = "WALLET_NEW_PASSPHRASE", hide_env_values = true)]  
+        new_passphrase: Option<String>,  
+    },  
+    /// Create a blockchain whose genesis reward goes to ADDRESS  
+    Createblockchain {  
+        address: String,  
+    },  
+    /// Print the balance of ADDRESS  
+    Getbalance {  
+        address: String,  
+    },  
+    /// Print the transactions on the main chain that paid or spent from ADDRESS  
+    Gethistory {  
+        address: String,  
+    },  
+    /// Send AMOUNT from FROM to TO; the transaction waits in the mempool until it is mined  
+    Send {  
+        from: String,  
+        to: String,  
+        amount: i32,  
+        /// Fee paid to the miner  
+        #[arg(long, default_value_t = 0)]  
+        fee: i32,  
+        /// Additional fee paid for every input the transaction spends  
+        #[arg(long, default_value_t = 0)]  
+        input_fee: i32,  
+        /// Coin selection strategy: largest-first, smallest-first, bnb or random  
+        #[arg(long, default_value = "bnb")]  
+        coin_selection: Strategy,  
+    },  
+    /// Mine a block from the mempool, paying the reward and fees to ADDRESS  
+    Mine {  
+        address: String,  
+        /// Number of mining threads, defaults to one per CPU core  
+        #[arg(long)]  
+        threads: Option<usize>,  
+    },  
+    /// List the pending transactions in the mempool by fee rate  
+    Listmempool,  
+    /// Print every block of the main chain  
+    Printchain,  
+    /// Rebuild the UTXO set from the main chain  
+    Reindexutxo,  
+    /// Compare the issued supply with the subsidy schedule  
+    Getsupply,  
+    /// Verify the whole chain from the genesis block  
+    Verifychain,  
+    /// Start a P2P node  
+    Startnode {  
+        /// Port to listen on  
+        #[arg(long, default_value_t = 3000)]  
+        port: u16,  
+        /// Address (host:port) of a node to connect to, can be repeated  
+        #[arg(long)]  
+        connect: Vec<String>,  
+        /// Mine a block for every new transaction, paying the reward to this address  
+        #[arg(long)]  
+        miner: Option<String>,  
+        /// Local port of the JSON-RPC server, defaults to the network's RPC port  
+        #[arg(long)]  
+        rpc_port: Option<u16>,  
+        /// Number of mining threads used with --miner, defaults to one per CPU core  
+        #[arg(long)]  
+        threads: Option<usize>,  
+    },  
+}  
+
+
+impl Default for CLI {  
+    fn default() -> Self {  
+        Self::new()  
+    }  
+}  
 
 impl CLI {   
     pub fn new() -> Self {  
         CLI {  
             blockchain: None, // 将 blockchain 初始化为 None 
             wallets: None,  
+            mempool: None,  
+            config: ChainConfig::default(),  
         }  
     } 
 
-    pub fn create_blockchain(&mut self, address: &String){
-        if !validate_address(address) {
-            println!("Invalid address");
-            process::exit(1);
+    pub fn create_blockchain(&mut self, address: &str) -> Result<()> {
+        if !validate_address(address, &self.config) {
+            return Err(Error::InvalidAddress(address.to_string()));
         }
-        let bc = BlockChain::new_blockchain(address);
+        if self.blockchain.is_some() {
+            return Err(Error::BlockchainExists(self.config.db_path()));
+        }
+        let bc = BlockChain::create(address, &self.config)?;
+        self.mempool = Some(Mempool::load(&bc, MAX_MEMPOOL_SIZE)?);
         self.blockchain = Some(bc);
-        let utxoset = UTXOSet {
-            blockchain: self.blockchain.as_ref().unwrap().clone(),
-        };
-        // println!("1 \n");
-        utxoset.reindex();
         println!("Done : Creating blockchain for address: {} \n", address);
         // self.blockchain = bc;
         // print!("cur blockchain: {:?}", self.blockchain);
+        Ok(())
     }
 
-    fn validate_args(&self) {  
-        let args: Vec<String> = env::args().collect();  
-        if args.len() < 2 {  
-            self.print_usage();  
-            process::exit(1);  
-        }  
-    }  
-
-    fn print_usage(&self) {  
-        println!("Usage: <command> <options>");  
-    }  
-
-    pub fn run(&mut self) {  
+    // 命令行入口: 解析参数, 加载数据目录中的钱包和区块链后执行子命令  
+    // 参数错误时由 clap 打印用法并退出, 命令失败时返回错误, 由 main 以非零状态退出  
+    pub fn run(&mut self) -> Result<()> {  
+        let args = Args::parse();  
+        self.config = ChainConfig::new(args.network, args.datadir);  
+        fs::create_dir_all(self.config.chain_dir())?;  
 
-        self.validate_args();
-
-        let args: Vec<String> = env::args().collect();  
+        self.create_wallets()?;  
+        self.open_blockchain()?;  
+        // 加密的钱包使用 --passphrase 或环境变量 WALLET_PASSPHRASE 解锁  
+        if let Some(ref passphrase) = args.passphrase {  
+            self.unlock_wallets(passphrase)?;  
+        }  
 
-        match args[1].as_str() {  
-            "getbalance" => {  
-                let address = args.get(2).expect("Address not provided");  
-                self.get_balance(address);  
+        match args.command {  
+            Command::Createwallet => self.create_wallet().map(|_| ()),  
+            Command::Listaddresses => self.list_addresses(),  
+            Command::Createhdwallet => self.create_hd_wallet(),  
+            Command::Exportmnemonic => self.export_mnemonic(),  
+            Command::Restorewallet { words } => self.restore_wallet(&words.join(" ")),  
+            Command::Encryptwallet => {  
+                let passphrase = new_passphrase_or_prompt(args.passphrase)?;  
+                self.encrypt_wallets(&passphrase)  
             }  
-            "createblockchain" => {  
-                let address = args.get(2).expect("Address not provided");  
-                self.create_blockchain(address);  
+            Command::Changepassphrase { new_passphrase } => {  
+                let old_passphrase = match args.passphrase {  
+                    Some(passphrase) => passphrase,  
+                    None => rpassword::prompt_password("Current passphrase: ")?,  
+                };  
+                let new_passphrase = new_passphrase_or_prompt(new_passphrase)?;  
+                self.change_passphrase(&old_passphrase, &new_passphrase)  
             }  
-            "printchain" => {  
-                self.print_chain();  
+            Command::Createblockchain { address } => self.create_blockchain(&address),  
+            Command::Getbalance { address } => self.get_balance(&address),  
+            Command::Gethistory { address } => self.get_history(&address),  
+            Command::Send { from, to, amount, fee, input_fee, coin_selection } => {  
+                self.send(&from, &to, amount, Fee { base: fee, per_input: input_fee }, coin_selection)  
             }  
-            "send" => {  
-                let from = args.get(2).expect("Source address not provided");  
-                let to = args.get(3).expect("Destination address not provided");  
-                let amount_str = args.get(4).expect("Amount not provided");  
-                let amount: i32 = amount_str.parse().expect("Invalid amount");  
-
-                self.send(from, to, amount);  
-            }  
-            _ => {  
-                self.print_usage();  
-                process::exit(1);  
+            Command::Mine { address, threads } => self.mine(&address, &threads.map_or_else(Miner::default, Miner::new)),  
+            Command::Listmempool => self.list_mempool(),  
+            Command::Printchain => self.print_chain(),  
+            Command::Reindexutxo => self.reindex_utxo(),  
+            Command::Getsupply => self.get_supply(),  
+            Command::Verifychain => self.verify_chain(),  
+            Command::Startnode { port, connect, miner, rpc_port, threads } => {  
+                let miner = miner.map(|address| (address, threads.map_or_else(Miner::default, Miner::new)));  
+                self.start_node(port, &connect, miner, rpc_port.unwrap_or(self.config.rpc_port))  
             }  
         }  
-    
     }
+
+    // 打开数据目录中已保存的区块链和内存池, 还没有区块链时保持为 None  
+    pub fn open_blockchain(&mut self) -> Result<()> {  
+        self.blockchain = match BlockChain::open(&self.config) {  
+            Ok(bc) => Some(bc),  
+            Err(Error::NoBlockchain) => None,  
+            Err(e) => return Err(e),  
+        };  
+        if let Some(ref bc) = self.blockchain {  
+            self.mempool = Some(Mempool::load(bc, MAX_MEMPOOL_SIZE)?);  
+        }  
+        Ok(())
+    }  
     
-    pub fn get_balance(&self, address: &str) {  
-        if !validate_address(&address) {
-            println!("Invalid address");
-            process::exit(1);
+    pub fn get_balance(&self, address: &str) -> Result<()> {  
+        if !validate_address(address, &self.config) {
+            return Err(Error::InvalidAddress(address.to_string()));
         }
         println!("Getting balance for address: {} \n", address);
         // print!("cur blockchain: {:?}", self.blockchain);
-        let bc = self.blockchain.as_ref().expect("Blockchain not found");  
+        let bc = self.blockchain.as_ref().ok_or(Error::NoBlockchain)?;  
         let utxoset = UTXOSet {
             blockchain: bc.clone(),
         };
-        let utxos = utxoset.find_utxos(address);  
+        // 旧版本的数据库没有地址索引, 需要先重建  
+        utxoset.migrate()?;  
+        let utxos = utxoset.find_utxos(address)?;  
 
         let balance: i32 = utxos.iter().map(|out| out.value).sum(); // 计算余额  
 
         println!("Balance of '{}': {}", address, balance);  
+        Ok(())
+    }  
+
+    pub fn get_history(&self, address: &str) -> Result<()> {  
+        if !validate_address(address, &self.config) {
+            return Err(Error::InvalidAddress(address.to_string()));
+        }
+        let bc = self.blockchain.as_ref().ok_or(Error::NoBlockchain)?;  
+        let utxoset = UTXOSet {
+            blockchain: bc.clone(),
+        };
+        utxoset.migrate()?;  
+        let pub_key_hash = functions::address_to_pubkeyhash(address)?;  
+        for (height, tx_id) in utxoset.address_history(&pub_key_hash)? {  
+            println!("{} {}", height, hex::encode(tx_id));  
+        }  
+        Ok(())
     }  
 
-    pub fn print_chain(&self) {  
+    pub fn print_chain(&self) -> Result<()> {  
         if let Some(ref bc) = self.blockchain {
             println!("current tips {:?} \n ", bc.tip);
             let mut bci = bc.iterator();
             loop {  
-                let block = bci.next(); // 获取下一个区块  
+                let block = bci.next().transpose()?; // 获取下一个区块  
 
                 match block {  
                     Some(block) => {  
                         // 打印区块的相关信息  
-                        println!("Prev. hash: {:?}", block.previous_block_hash);  
+                        if let Some(index) = bc.get_index(&block.hash)? {  
+                            println!("Height: {}", index.height);  
+                        }  
+                        println!("Prev. hash: {:?}", block.header.prev_block_hash);  
+                        println!("Merkle root: {:?}", block.header.merkle_root);  
                         // println!("transactions: {}", block.transactions);  
                         println!("Hash: {:?}", block.hash);  
                         
-                        let pow = ProofOfWork::new(&block); // 创建工作量证明实例  
-                        println!("PoW: {}", pow.validate()); // 打印 PoW 验证结果  
+                        let pow = ProofOfWork::new(&block.header); // 创建工作量证明实例  
+                        let expected_bits = bc.expected_bits(&block.header.prev_block_hash)?;  
+                        println!("PoW: {}", pow.validate(expected_bits)); // 打印 PoW 验证结果  
                         println!();  
                         
                         // 检查前一个区块哈希是否为空  
-                        if block.previous_block_hash.is_empty() {  
+                        if block.header.prev_block_hash.is_empty() {  
                             break;  
                         }  
                     },  
@@ -137,47 +302,231 @@ impl CLI {
                 }  
             }  
         }
+        Ok(())
     } 
 
-    pub fn send(&mut self, from: &String, to: &String, amount: i32) {  
-        let wallets = self.wallets.as_ref().expect("wallets not found");
-        if let Some(ref mut block_chain) = self.blockchain {  
-            // let tx = Transaction::new_utxo_transaction(&from, &to, amount, &block_chain, &wallets, &UTXOSet);
-            let utxoset = UTXOSet{
-                blockchain: block_chain.clone(),
-            };
-            let tx = Transaction::new_utxo_transaction(
-                &from, &to, amount, 
-                &block_chain, 
-                &wallets,
-                &utxoset
-            );
-            let cbTX = Transaction::new_coinbase_transcation(from, &String::from("Reward"));
-            let txs: Vec<Transaction> = vec![tx, cbTX]; 
-            let newblock = block_chain.MineBlock(txs); 
-            utxoset.update(&newblock);
-            println!("Success send!");  
-        } else {  
-            eprintln!("Error: BlockChain is None");  
+    pub fn get_supply(&self) -> Result<()> {  
+        let bc = self.blockchain.as_ref().ok_or(Error::NoBlockchain)?;  
+        let height = bc.height()?;  
+        let issued = bc.issued_supply()?;  
+        let scheduled = functions::scheduled_supply(&bc.config, height);  
+        println!("Height: {}", height);  
+        println!("Issued supply: {}", issued);  
+        println!("Scheduled supply: {} (cap {})", scheduled, functions::max_supply(&bc.config));  
+        if issued > scheduled {  
+            return Err(Error::SupplyExceeded { issued, scheduled });  
+        }  
+        Ok(())
+    }  
+
+    pub fn reindex_utxo(&self) -> Result<()> {  
+        let bc = self.blockchain.as_ref().ok_or(Error::NoBlockchain)?;  
+        let utxoset = UTXOSet {  
+            blockchain: bc.clone(),  
+        };  
+        utxoset.reindex()?;  
+        println!("Done! There are {} transactions in the UTXO set.", utxoset.count_transactions()?);  
+        Ok(())  
+    }  
+
+    // 没有区块链时从空数据库启动, 从连接的节点同步包括创世区块在内的整条链  
+    // 同时在 rpc_port 上启动本地 JSON-RPC 服务, 认证信息写入数据目录中的 cookie 文件  
+    pub fn start_node(&mut self, port: u16, seeds: &[String], miner: Option<(String, Miner)>, rpc_port: u16) -> Result<()> {  
+        if let Some((ref address, _)) = miner {  
+            if !validate_address(address, &self.config) {  
+                return Err(Error::InvalidAddress(address.to_string()));  
+            }  
+        }  
+        let bc = match self.blockchain.take() {  
+            Some(bc) => bc,  
+            None => BlockChain::open_or_init(&self.config)?,  
+        };  
+        if !bc.tip.is_empty() {  
+            UTXOSet {  
+                blockchain: bc.clone(),  
+            }  
+            .migrate()?;  
+        }  
+        let mempool = match self.mempool.take() {  
+            Some(mempool) => mempool,  
+            None => Mempool::load(&bc, MAX_MEMPOOL_SIZE)?,  
+        };  
+        let node = Node::new(bc, mempool, miner);  
+        let wallets = self.wallets.take().unwrap_or_else(|| Wallets::new(&self.config));  
+        RpcServer::new(node.clone(), wallets, &self.config)?.spawn(rpc_port)?;  
+        node.run(port, seeds)  
+    }  
+
+    pub fn verify_chain(&self) -> Result<()> {  
+        let bc = self.blockchain.as_ref().ok_or(Error::NoBlockchain)?;  
+        let count = chain_verifier::verify_chain(bc)?;  
+        println!("Chain is valid: {} blocks verified", count);  
+        Ok(())
+    }  
+
+    // 构造并签名交易后放入内存池, 由 mine 命令打包  
+    pub fn send(&mut self, from: &str, to: &str, amount: i32, fee: Fee, strategy: Strategy) -> Result<()> {  
+        let wallets = self.wallets.as_ref().ok_or_else(|| Error::WalletNotFound(from.to_string()))?;
+        let block_chain = self.blockchain.as_ref().ok_or(Error::NoBlockchain)?;  
+        let mempool = self.mempool.as_mut().ok_or(Error::NoBlockchain)?;  
+        let utxoset = UTXOSet{
+            blockchain: block_chain.clone(),
+        };
+        utxoset.migrate()?;
+        let tx = Transaction::new_utxo_transaction(
+            from, to, amount, fee, 
+            strategy.selector().as_ref(), 
+            wallets,
+            &utxoset,
+            mempool
+        )?;
+        let tx_id = tx.id.clone();
+        mempool.add(tx, block_chain)?;
+        println!("Transaction {} added to the mempool", hex::encode(tx_id));  
+        Ok(())
+    }  
+
+    // 按手续费率从内存池选出交易, 加上 coinbase 后挖出新区块  
+    pub fn mine(&mut self, address: &str, miner: &Miner) -> Result<()> {  
+        if !validate_address(address, &self.config) {
+            return Err(Error::InvalidAddress(address.to_string()));
         }
+        let block_chain = self.blockchain.as_mut().ok_or(Error::NoBlockchain)?;  
+        let mempool = self.mempool.as_mut().ok_or(Error::NoBlockchain)?;  
+        UTXOSet{
+            blockchain: block_chain.clone(),
+        }.migrate()?;
 
+        let newblock = mempool.mine(block_chain, address, miner)?;
+        println!(
+            "Mined block {} at height {} with {} transactions ({} on {} threads)",
+            hex::encode(&newblock.hash),
+            block_chain.height()?,
+            newblock.transactions.len() - 1,
+            format_hash_rate(miner.hash_rate()),
+            miner.threads()
+        );  
+        Ok(())
     }  
 
-    pub fn create_wallets(&mut self) {
-        let wallets = Wallets::new();
+    pub fn list_mempool(&self) -> Result<()> {  
+        let mempool = self.mempool.as_ref().ok_or(Error::NoBlockchain)?;  
+        println!("{} transactions, {} bytes", mempool.len(), mempool.size());  
+        for entry in mempool.entries() {  
+            println!("{} fee: {} size: {}", hex::encode(&entry.tx.id), entry.fee, entry.size);  
+        }  
+        Ok(())
+    }  
+
+    // 从钱包文件加载已有的钱包  
+    pub fn create_wallets(&mut self) -> Result<()> {
+        let wallets = Wallets::load_from_file(&self.config)?;
         self.wallets = Some(wallets);
+        Ok(())
     }  
 
-    pub fn create_wallet(&mut self) -> String{
-        let wallets = self.wallets.as_mut().expect("Wallets not found");
+    pub fn create_wallet(&mut self) -> Result<String> {
+        let config = &self.config;
+        let wallets = self.wallets.get_or_insert_with(|| Wallets::new(config));
         
-        let address = wallets.new_wallet();
+        let address = wallets.new_wallet()?;
 
-        // wallets.save_to_file(wallet_file_path);
+        wallets.save_to_file()?;
 
         println!("Your wallet address: {}", &address);
 
-        address
+        Ok(address)
+    }
+
+    pub fn encrypt_wallets(&mut self, passphrase: &str) -> Result<()> {
+        let config = &self.config;
+        let wallets = self.wallets.get_or_insert_with(|| Wallets::new(config));
+        wallets.encrypt(passphrase)?;
+        wallets.save_to_file()?;
+        wallets.lock();
+        println!("Wallet encrypted");
+        Ok(())
     }
 
-}  
\ No newline at end of file
+    // 未加密的钱包不需要解锁  
+    pub fn unlock_wallets(&mut self, passphrase: &str) -> Result<()> {
+        match self.wallets {
+            Some(ref mut wallets) if wallets.is_encrypted() => wallets.unlock(passphrase),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn lock_wallets(&mut self) {
+        if let Some(ref mut wallets) = self.wallets {
+            wallets.lock();
+        }
+    }
+
+    pub fn change_passphrase(&mut self, old_passphrase: &str, new_passphrase: &str) -> Result<()> {
+        let config = &self.config;
+        let wallets = self.wallets.get_or_insert_with(|| Wallets::new(config));
+        wallets.change_passphrase(old_passphrase, new_passphrase)?;
+        wallets.save_to_file()?;
+        wallets.lock();
+        println!("Passphrase changed");
+        Ok(())
+    }
+
+    // 生成助记词并打印, 之后 createwallet 从它派生地址  
+    pub fn create_hd_wallet(&mut self) -> Result<()> {
+        let config = &self.config;
+        let wallets = self.wallets.get_or_insert_with(|| Wallets::new(config));
+        let mnemonic = wallets.create_hd()?;
+        wallets.save_to_file()?;
+        println!("Write down your mnemonic: {}", mnemonic);
+        Ok(())
+    }
+
+    pub fn export_mnemonic(&self) -> Result<()> {
+        let wallets = self.wallets.as_ref().ok_or_else(|| Error::HdWallet("wallet has no HD seed".to_string()))?;
+        println!("{}", wallets.export_mnemonic()?);
+        Ok(())
+    }
+
+    // 从助记词恢复 HD 钱包, 扫描主链找回使用过的地址和余额  
+    pub fn restore_wallet(&mut self, mnemonic: &str) -> Result<()> {
+        let config = &self.config;
+        let wallets = self.wallets.get_or_insert_with(|| Wallets::new(config));
+        wallets.import_mnemonic(mnemonic)?;
+        if let Some(ref bc) = self.blockchain {
+            let used = bc.used_pubkey_hashes()?;
+            let addresses = wallets.scan(GAP_LIMIT, |pub_key_hash| used.contains(pub_key_hash))?;
+            wallets.save_to_file()?;
+            println!("Restored {} used addresses", addresses.len());
+            for address in &addresses {
+                self.get_balance(address)?;
+            }
+        } else {
+            wallets.save_to_file()?;
+            println!("Mnemonic imported; no blockchain to scan for used addresses");
+        }
+        Ok(())
+    }
+
+    pub fn list_addresses(&self) -> Result<()> {
+        if let Some(ref wallets) = self.wallets {
+            for address in wallets.get_addresses() {
+                println!("{}", address);
+            }
+        }
+        Ok(())
+    }
+
+}  
+
+// 口令不出现在位置参数中: 使用命令行选项或环境变量给出的值, 否则在终端中输入两次  
+fn new_passphrase_or_prompt(passphrase: Option<String>) -> Result<String> {  
+    if let Some(passphrase) = passphrase {  
+        return Ok(passphrase);  
+    }  
+    let passphrase = rpassword::prompt_password("New passphrase: ")?;  
+    if rpassword::prompt_password("Repeat passphrase: ")? != passphrase {  
+        return Err(Error::WalletEncryption("passphrases do not match".to_string()));  
+    }  
+    Ok(passphrase)
+}  
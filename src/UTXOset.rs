@@ -1,138 +1,502 @@
-use sled::Db;
-use crate::block_chain::BlockChain;
-use crate::DB_FILE;
+use crate::block_chain::{BlockChain, ChainSwitch};
 use crate::block::Block;
+use crate::coin_selection::Candidate;
+use crate::error::{Error, Result};
 use crate::functions;
 
-use std::collections::HashMap;  
-use std::error::Error;  
-use hex;  
-use crate::transactions::{TXInput, TXOutput, TXOutputs};
+use std::collections::{HashMap, HashSet};  
+use serde::{Serialize, Deserialize};
+use sled::transaction::{ConflictableTransactionError, ConflictableTransactionResult};
+use sled::Transactional;
+use crate::transactions::{TXOutput, Transaction};
+
+// utxoBucket 存储格式的版本号, 保存在数据库的 UTXO_FORMAT_KEY 下  
+const UTXO_FORMAT_KEY: &str = "utxo_format";  
+// 版本 3 加入了 addressIndex 和 addressHistory 两个按地址的索引  
+const UTXO_FORMAT_VERSION: u8 = 3;  
+
+// UTXO 集对应的区块, 与每个区块的 UTXO 修改在同一个事务中写入  
+// 打开区块链时与 tip 比较, 不一致说明上次在更新 UTXO 集和保存 tip 之间中断了  
+pub const UTXO_TIP_KEY: &str = "utxo_tip";  
+
+// addressIndex: 公钥哈希 -> 它拥有的未花费输出, 值为空, 输出本身仍在 utxoBucket 中  
+const ADDRESS_INDEX: &str = "addressIndex";  
+// addressHistory: 公钥哈希 -> 主链上收到或花费过它的输出的交易, 按高度排列  
+const ADDRESS_HISTORY: &str = "addressHistory";  
+
+// 一个地址拥有的未花费输出: (交易 id, 输出序号, 记录)  
+pub type AddressUnspent = Vec<(Vec<u8>, usize, UTXOEntry)>;  
 
 pub struct UTXOSet {  
     pub blockchain: BlockChain,  
 }  
 
+// utxoBucket 中的一条记录: 未花费输出, 以及产生它的区块高度和是否来自 coinbase  
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]  
+pub struct UTXOEntry {  
+    pub output: TXOutput,  
+    pub height: u64,  
+    pub is_coinbase: bool,  
+}  
+
+impl UTXOEntry {  
+    pub fn serialize(&self) -> Vec<u8> {  
+        bincode::serialize(self).expect("Serialization failed")  
+    }  
+
+    pub fn deserialize_entry(data: &[u8]) -> Result<UTXOEntry> {  
+        Ok(bincode::deserialize(data)?)
+    }  
+
+    // coinbase 输出要在产生它的区块之后经过 maturity (ChainConfig::coinbase_maturity) 个区块才能花费  
+    pub fn is_mature(&self, spend_height: u64, maturity: u64) -> bool {  
+        !self.is_coinbase || spend_height.saturating_sub(self.height) >= maturity  
+    }  
+}  
+
+// 被某个输入花费掉的记录, 以及它原来的 (交易 id, 输出序号)  
+#[derive(Serialize, Deserialize, Debug, Clone)]  
+pub struct SpentOutput {  
+    pub transcation_id: Vec<u8>,  
+    pub vout: usize,  
+    pub entry: UTXOEntry,  
+}  
+
+// 一个区块的撤销数据: 按花费顺序记录 update 从 utxoBucket 中删除的输出  
+#[derive(Serialize, Deserialize, Debug, Default)]  
+pub struct BlockUndo {  
+    pub spent: Vec<SpentOutput>,  
+}  
+
 
 impl UTXOSet {  
 
-    pub fn update(&self, block: &Block) {  
+    // 检查 utxoBucket 的存储格式, 旧格式 (按交易 id 保存压缩后的 TXOutputs, 输出序号会错位;  
+    // 或者没有记录高度和 coinbase 标记) 无法可靠地转换, 直接沿主链重建  
+    // 旧格式的撤销数据同样无法使用, 一并删除, 之后的重组会退回到 reindex  
+    // UTXO 集不对应 tip 时 (没有记录 UTXO_TIP_KEY 的旧数据库, 或者 reindex 被中断) 同样沿主链重建  
+    pub fn migrate(&self) -> Result<()> {  
+        let db = &self.blockchain.db;  
+        let format = db.get(UTXO_FORMAT_KEY)?;  
+        if format.as_deref() != Some(&[UTXO_FORMAT_VERSION][..]) {  
+            db.drop_tree("undo")?;  
+            self.reindex()?;  
+        } else if db.get(UTXO_TIP_KEY)?.as_deref() != Some(&self.blockchain.tip[..]) {  
+            self.reindex()?;  
+        }  
+        Ok(())
+    }  
+
+    // 在一个事务中更新 utxoBucket, 两个地址索引和撤销数据, 并把 UTXO_TIP_KEY 指向这个区块  
+    pub fn update(&self, block: &Block) -> Result<()> {  
         let db = &self.blockchain.db;  
-        let bucket = db.open_tree("utxoBucket").expect("Failed to open utxoBucket");  
+        let bucket = db.open_tree("utxoBucket")?;  
+        let address_index = db.open_tree(ADDRESS_INDEX)?;  
+        let history = db.open_tree(ADDRESS_HISTORY)?;  
+        let undo_tree = db.open_tree("undo")?;  
+        let height = self.blockchain.get_index(&block.hash)?  
+            .ok_or_else(|| Error::BlockNotFound(block.hash.clone()))?  
+            .height;  
 
-            for transaction in &block.transactions {  
-                if !transaction.is_coinbase() {  
-                    for vin in &transaction.inputs {  
-                        let mut updated_outs = TXOutputs { outputs: vec![] };  
-                        
-                        if let Some(outs_bytes) = bucket.get(&vin.transcation_id).unwrap() { 
-                            let outs = match bincode::deserialize::<TXOutputs>(&outs_bytes) {  
-                                Ok(outs) => outs,  
-                                Err(e) => {  
-                                    // log::error!("Failed to deserialize TXOutputs: {:?}", e);  
-                                    continue;   
-                                },  
-                            };  
-
-                            for (out_idx, out) in outs.outputs.iter().enumerate() {  
-                                if out_idx != vin.vout {  
-                                    updated_outs.outputs.push(out.clone());  
-                                }  
+        (&**db, &bucket, &address_index, &history, &undo_tree).transaction(  
+            |(meta, bucket, address_index, history, undo_tree)| -> ConflictableTransactionResult<(), Error> {  
+                let mut undo = BlockUndo::default();  
+                for transaction in &block.transactions {  
+                    if !transaction.is_coinbase() {  
+                        for vin in &transaction.inputs {  
+                            let key = outpoint_key(&vin.transcation_id, vin.vout);  
+                            if let Some(entry_bytes) = bucket.remove(key.as_slice())? {  
+                                let entry = UTXOEntry::deserialize_entry(&entry_bytes).map_err(abort)?;  
+                                let owner = &entry.output.PubKeyHash;  
+                                address_index.remove(address_key(owner, &key))?;  
+                                history.insert(address_key(owner, &history_suffix(height, &transaction.id)), vec![])?;  
+                                undo.spent.push(SpentOutput {  
+                                    transcation_id: vin.transcation_id.clone(),  
+                                    vout: vin.vout,  
+                                    entry,  
+                                });  
                             }  
-                        } else {  
-                            continue;  
                         }  
-                        
-                        if updated_outs.outputs.is_empty() {  
-                            bucket.remove(&vin.transcation_id).expect("Failed to delete UTXO");  
-                        } else {  
-                            bucket.insert(&vin.transcation_id, updated_outs.serialize()).expect("Failed to update UTXO");  
+                    }  
+                    for (out_idx, out) in transaction.outputs.iter().enumerate() {  
+                        let entry = UTXOEntry {  
+                            output: out.clone(),  
+                            height,  
+                            is_coinbase: transaction.is_coinbase(),  
+                        };  
+                        let key = outpoint_key(&transaction.id, out_idx);  
+                        // 相同 id 的交易会覆盖仍未花费的输出, 拒绝整个区块  
+                        if bucket.insert(key.clone(), entry.serialize())?.is_some() {  
+                            return Err(abort(Error::InvalidBlock(format!(  
+                                "transaction {} overwrites unspent output {}",  
+                                hex::encode(&transaction.id),  
+                                out_idx  
+                            ))));  
                         }  
+                        address_index.insert(address_key(&out.PubKeyHash, &key), vec![])?;  
+                        history.insert(address_key(&out.PubKeyHash, &history_suffix(height, &transaction.id)), vec![])?;  
                     }  
                 }  
-                let new_outputs = TXOutputs {  
-                    outputs: transaction.outputs.clone(),  
-                };  
-                bucket.insert(&transaction.id, new_outputs.serialize()).expect("Failed to insert new UTXO");  
-            }  
+
+                let encoded = bincode::serialize(&undo).map_err(abort)?;  
+                undo_tree.insert(block.hash.as_slice(), encoded)?;  
+                meta.insert(UTXO_TIP_KEY, block.hash.as_slice())?;  
+                Ok(())
+            },  
+        )?;  
+        Ok(())
     }
 
-    pub fn find_utxos(&self, address: &str) -> Vec<TXOutput> {  
-        let blocks_bucket = self.blockchain.db.open_tree("blocks").expect("Failed to open blocks tree");  
-        let queryPubHash_from_address = functions::address_to_pubkeyhash(address);
-        let mut utxos = Vec::new();  
+    // 撤销 update 对一个区块做的修改: 删除区块产生的输出, 按相反顺序放回被花费的输出  
+    // 与 update 一样在一个事务中完成, UTXO_TIP_KEY 退回到父区块  
+    // 没有该区块的撤销数据时返回 false, 不修改 utxoBucket  
+    pub fn revert(&self, block: &Block) -> Result<bool> {  
+        let db = &self.blockchain.db;  
+        let bucket = db.open_tree("utxoBucket")?;  
+        let address_index = db.open_tree(ADDRESS_INDEX)?;  
+        let history = db.open_tree(ADDRESS_HISTORY)?;  
+        let undo_tree = db.open_tree("undo")?;  
+        let height = self.blockchain.get_index(&block.hash)?  
+            .ok_or_else(|| Error::BlockNotFound(block.hash.clone()))?  
+            .height;  
 
-        for result in blocks_bucket.iter() {  
-            if let Ok((key, value)) = result {  
-                let block: Block = bincode::deserialize(&value).expect("Failed to deserialize Block");  
+        let reverted = (&**db, &bucket, &address_index, &history, &undo_tree).transaction(  
+            |(meta, bucket, address_index, history, undo_tree)| -> ConflictableTransactionResult<bool, Error> {  
+                let undo: BlockUndo = match undo_tree.get(&block.hash)? {  
+                    Some(bytes) => bincode::deserialize(&bytes).map_err(abort)?,  
+                    None => return Ok(false),  
+                };  
 
-                for transaction in block.transactions {  
-                    for output in transaction.outputs {  
-                        if output.is_locked_with_key(&queryPubHash_from_address) {  
-                            utxos.push(output.clone());  
+                for transaction in block.transactions.iter().rev() {  
+                    for (out_idx, out) in transaction.outputs.iter().enumerate() {  
+                        let key = outpoint_key(&transaction.id, out_idx);  
+                        bucket.remove(key.as_slice())?;  
+                        address_index.remove(address_key(&out.PubKeyHash, &key))?;  
+                        history.remove(address_key(&out.PubKeyHash, &history_suffix(height, &transaction.id)))?;  
+                    }  
+                    // 花费的输出在撤销数据中, 它们的主人同样要删除这笔交易的历史  
+                    for vin in &transaction.inputs {  
+                        if let Some(spent) = undo.spent.iter().find(|spent| {  
+                            spent.transcation_id == vin.transcation_id && spent.vout == vin.vout  
+                        }) {  
+                            let owner = &spent.entry.output.PubKeyHash;  
+                            history.remove(address_key(owner, &history_suffix(height, &transaction.id)))?;  
                         }  
                     }  
                 }  
+
+                for spent in undo.spent.iter().rev() {  
+                    let key = outpoint_key(&spent.transcation_id, spent.vout);  
+                    address_index.insert(address_key(&spent.entry.output.PubKeyHash, &key), vec![])?;  
+                    bucket.insert(key, spent.entry.serialize())?;  
+                }  
+
+                undo_tree.remove(block.hash.as_slice())?;  
+                meta.insert(UTXO_TIP_KEY, block.header.prev_block_hash.as_slice())?;  
+                Ok(true)
+            },  
+        )?;  
+        Ok(reverted)
+    }
+
+    // 按 BlockChain::add_block 找到的主链变化更新 UTXO 集, self.blockchain 指向变化前的 tip  
+    // 接入的区块逐个按它接入时的 UTXO 状态检查交易后再更新; 有无效区块时恢复原来的 UTXO 集, 返回该区块的错误  
+    pub fn apply(&mut self, switch: &ChainSwitch) -> Result<()> {  
+        // 发生了重组: 先从旧 tip 依次撤销到分叉点  
+        for block in &switch.disconnected {  
+            self.blockchain.tip = block.header.prev_block_hash.clone();  
+            if !self.revert(block)? {  
+                // 缺少撤销数据 (例如旧版本写入的区块), 沿分叉点之前的主链重建  
+                if let Some(last) = switch.disconnected.last() {  
+                    self.blockchain.tip = last.header.prev_block_hash.clone();  
+                }  
+                self.reindex()?;  
+                break;  
             }  
         }  
-        utxos  
+
+        for (applied, block) in switch.connected.iter().enumerate() {  
+            let result = self.blockchain.check_transactions(&block.transactions).and_then(|_| self.update(block));  
+            if let Err(e) = result {  
+                self.restore(switch, applied)?;  
+                // 区块本身无效 (而不是读写出错) 时, 它和它的后代不会再被接入  
+                if !e.is_storage() {  
+                    self.blockchain.mark_invalid(&block.hash)?;  
+                }  
+                return Err(e);  
+            }  
+            self.blockchain.tip = block.hash.clone();  
+        }  
+        Ok(())
+    }  
+
+    // 撤销 apply 已经接入的前 applied 个区块, 再按原来的顺序接回断开的区块  
+    fn restore(&mut self, switch: &ChainSwitch, applied: usize) -> Result<()> {  
+        for block in switch.connected[..applied].iter().rev() {  
+            self.revert(block)?;  
+        }  
+        for block in switch.disconnected.iter().rev() {  
+            self.update(block)?;  
+        }  
+        Ok(())
+    }  
+
+    // 读取 (交易 id, 输出序号) 对应的未花费输出, 已花费或不存在时返回 None  
+    pub fn get_entry(&self, tx_id: &[u8], vout: usize) -> Result<Option<UTXOEntry>> {  
+        let bucket = self.blockchain.db.open_tree("utxoBucket")?;  
+        match bucket.get(outpoint_key(tx_id, vout))? {  
+            Some(bytes) => Ok(Some(UTXOEntry::deserialize_entry(&bytes)?)),  
+            None => Ok(None),  
+        }  
+    }  
+
+    // 通过 addressIndex 查出公钥哈希拥有的全部未花费输出, 包括尚未成熟的 coinbase 输出  
+    pub fn find_unspent(&self, pub_key_hash: &[u8]) -> Result<AddressUnspent> {  
+        let db = &self.blockchain.db;  
+        let bucket = db.open_tree("utxoBucket")?;  
+        let address_index = db.open_tree(ADDRESS_INDEX)?;  
+        let mut unspent = Vec::new();  
+
+        for key in address_index.scan_prefix(address_prefix(pub_key_hash)).keys() {  
+            let key = key?;  
+            let outpoint = &key[address_prefix(pub_key_hash).len()..];  
+            let (tx_id, out_idx) = split_outpoint_key(outpoint);  
+            let entry_bytes = bucket.get(outpoint)?  
+                .ok_or_else(|| Error::TxNotFound(tx_id.clone()))?;  
+            unspent.push((tx_id, out_idx, UTXOEntry::deserialize_entry(&entry_bytes)?));  
+        }  
+        Ok(unspent)
+    }  
+
+    pub fn find_utxos(&self, address: &str) -> Result<Vec<TXOutput>> {  
+        let query_pub_hash = functions::address_to_pubkeyhash(address)?;
+        let utxos = self.find_unspent(&query_pub_hash)?  
+            .into_iter()  
+            .map(|(_, _, entry)| entry.output)  
+            .collect();  
+        Ok(utxos)
     }   
     
-    pub fn find_spendable_outputs(
-        &self, 
-        pubkey_hash: &Vec<u8>, 
-        amount: i32
-    ) -> (i32, HashMap<Vec<u8>, Vec<usize>>) {  
-        let utxo_bucket = self.blockchain.db.open_tree("utxoBucket").expect("Failed to open UTXO bucket");  
-        let mut unspent_outputs: HashMap<Vec<u8>, Vec<usize>> = HashMap::new();  
-        let mut accumulated: i32 = 0;  
-        for result in utxo_bucket.iter() {  
-            if let Ok((key, value)) = result {  
-                let tx_id = key.to_vec();  
-                let outs: TXOutputs = bincode::deserialize(&value).expect("Failed to deserialize Outputs"); 
-    
-                for (out_idx, out) in outs.outputs.iter().enumerate() {  
-                    if out.is_locked_with_key(pubkey_hash) && accumulated < amount {  
-                        accumulated += out.value;  
-                        unspent_outputs.entry(tx_id.clone()).or_insert_with(Vec::new).push(out_idx);  
-                    }  
-                }  
-                if accumulated >= amount {  
-                    break;  
-                }  
+    // 公钥哈希拥有的, 在下一个区块中可以花费的输出, 跳过尚未成熟的 coinbase 输出  
+    pub fn find_spendable(&self, pubkey_hash: &[u8]) -> Result<Vec<Candidate>> {  
+        // 新交易最早被打包进下一个区块  
+        let spend_height = self.blockchain.height()? + 1;  
+        let candidates = self.find_unspent(pubkey_hash)?  
+            .into_iter()  
+            .filter(|(_, _, entry)| entry.is_mature(spend_height, self.blockchain.config.coinbase_maturity))  
+            .map(|(tx_id, vout, entry)| Candidate { tx_id, vout, value: entry.output.value })  
+            .collect();  
+        Ok(candidates)
+    }  
 
-            }
-        }(accumulated, unspent_outputs)  
+    // 地址的交易历史: 主链上向它付款或花费它的输出的 (高度, 交易 id), 按高度升序  
+    pub fn address_history(&self, pub_key_hash: &[u8]) -> Result<Vec<(u64, Vec<u8>)>> {  
+        let history = self.blockchain.db.open_tree(ADDRESS_HISTORY)?;  
+        let prefix = address_prefix(pub_key_hash);  
+        let mut txs = Vec::new();  
+        for key in history.scan_prefix(&prefix).keys() {  
+            let key = key?;  
+            let (height, tx_id) = key[prefix.len()..].split_at(8);  
+            let height = u64::from_be_bytes(height.try_into().expect("Invalid history key"));  
+            txs.push((height, tx_id.to_vec()));  
+        }  
+        Ok(txs)
     }  
 
+    // addressIndex 必须正好索引 utxoBucket 中的每个输出  
+    pub fn check_address_index(&self) -> Result<bool> {  
+        let db = &self.blockchain.db;  
+        let address_index = db.open_tree(ADDRESS_INDEX)?;  
+        let utxo = self.utxo_map()?;  
+        if address_index.len() != utxo.len() {  
+            return Ok(false);  
+        }  
+        for ((tx_id, out_idx), entry) in &utxo {  
+            let key = address_key(&entry.output.PubKeyHash, &outpoint_key(tx_id, *out_idx));  
+            if !address_index.contains_key(key)? {  
+                return Ok(false);  
+            }  
+        }  
+        Ok(true)
+    }  
+
+    // 读出 utxoBucket 中保存的全部未花费输出, 以 (交易 id, 输出序号) 为键  
+    pub fn utxo_map(&self) -> Result<HashMap<(Vec<u8>, usize), UTXOEntry>> {  
+        let bucket = self.blockchain.db.open_tree("utxoBucket")?;  
+        let mut utxo = HashMap::new();  
+        for item in bucket.iter() {  
+            let (key, value) = item?;  
+            utxo.insert(split_outpoint_key(&key), UTXOEntry::deserialize_entry(&value)?);  
+        }  
+        Ok(utxo)
+    }  
 
-    pub fn count_transactions(&self) -> usize {  
+    // 统计还有未花费输出的交易数  
+    pub fn count_transactions(&self) -> Result<usize> {  
         let db = &self.blockchain.db;  
         let mut counter = 0;  
+        let mut last_tx_id: Option<Vec<u8>> = None;  
 
-        let bucket = db.open_tree("utxoBucket").expect("Failed to open UTXO bucket");  
-            for _ in bucket.iter() {  
-                counter += 1; // 每个输出计数  
+        let bucket = db.open_tree("utxoBucket")?;  
+            for key in bucket.iter().keys() {  
+                let (tx_id, _) = split_outpoint_key(&key?);  
+                if last_tx_id.as_ref() != Some(&tx_id) {  
+                    counter += 1;  
+                    last_tx_id = Some(tx_id);  
+                }  
             }  
-        counter  
+        Ok(counter)
     }  
 
-    // 重新索引 UTXO 集  
-    pub fn reindex(&self) {  
-        // println!("1 \n");
+    // 重新索引 UTXO 集以及按地址的两个索引  
+    pub fn reindex(&self) -> Result<()> {  
         let db = &self.blockchain.db;  
-        let bucket_name = "utxoBucket";  
 
-        db.drop_tree(bucket_name);
+        // 重建完成前中断时, 下次打开由 migrate 重新开始  
+        db.remove(UTXO_FORMAT_KEY)?;  
+        db.remove(UTXO_TIP_KEY)?;  
+        db.drop_tree("utxoBucket")?;
+        db.drop_tree(ADDRESS_INDEX)?;
+        db.drop_tree(ADDRESS_HISTORY)?;
 
-        let bucket = db.open_tree("utxoBucket").expect("Failed to create UTXO bucket");  
+        let bucket = db.open_tree("utxoBucket")?;  
+        let address_index = db.open_tree(ADDRESS_INDEX)?;  
 
-        let utxo = self.blockchain.find_utxo(); 
-        // println!("find utxo {:?}\n", utxo);
-        for (tx_id, outs) in utxo {  
-            let key = tx_id;
-            let encoded_outputs = outs.serialize(); 
-            bucket.insert(key, encoded_outputs).expect("Failed to insert output");  
+        let utxo = self.blockchain.find_utxo()?; 
+        for ((tx_id, out_idx), entry) in utxo {  
+            let key = outpoint_key(&tx_id, out_idx);
+            address_index.insert(address_key(&entry.output.PubKeyHash, &key), vec![])?;  
+            bucket.insert(key, entry.serialize())?;  
         }  
+        self.reindex_history()?;  
+        db.insert(UTXO_FORMAT_KEY, &[UTXO_FORMAT_VERSION])?;  
+        db.insert(UTXO_TIP_KEY, self.blockchain.tip.as_slice())?;  
+        Ok(())
     }  
+
+    // 从创世区块沿主链向前重建 addressHistory, 花费的输出按交易 id 在已经经过的交易中查找  
+    fn reindex_history(&self) -> Result<()> {  
+        let history = self.blockchain.db.open_tree(ADDRESS_HISTORY)?;  
+        let mut known_txs: HashMap<Vec<u8>, Transaction> = HashMap::new();  
+        for block in &self.blockchain.main_chain_blocks()? {  
+            let height = self.blockchain.get_index(&block.hash)?.map(|index| index.height).unwrap_or(0);  
+            for tx in &block.transactions {  
+                let mut owners: HashSet<&Vec<u8>> = tx.outputs.iter().map(|out| &out.PubKeyHash).collect();  
+                if !tx.is_coinbase() {  
+                    for vin in &tx.inputs {  
+                        if let Some(prev_tx) = known_txs.get(&vin.transcation_id) {  
+                            owners.insert(&prev_tx.outputs[vin.vout].PubKeyHash);  
+                        }  
+                    }  
+                }  
+                for owner in owners {  
+                    history.insert(address_key(owner, &history_suffix(height, &tx.id)), vec![])?;  
+                }  
+            }  
+            for tx in &block.transactions {  
+                known_txs.insert(tx.id.clone(), tx.clone());  
+            }  
+        }  
+        Ok(())
+    }  
+}
+
+// 在事务中中止并返回库的错误  
+fn abort(e: impl Into<Error>) -> ConflictableTransactionError<Error> {  
+    ConflictableTransactionError::Abort(e.into())  
+}  
+
+// utxoBucket 的键: 交易 id 后接 8 字节大端的输出序号, 同一交易的输出按序号相邻  
+pub fn outpoint_key(tx_id: &[u8], vout: usize) -> Vec<u8> {  
+    let mut key = tx_id.to_vec();  
+    key.extend_from_slice(&(vout as u64).to_be_bytes());  
+    key  
+}  
+
+pub fn split_outpoint_key(key: &[u8]) -> (Vec<u8>, usize) {  
+    let (tx_id, vout) = key.split_at(key.len() - 8);  
+    let vout = u64::from_be_bytes(vout.try_into().expect("Invalid outpoint key"));  
+    (tx_id.to_vec(), vout as usize)  
+}
+
+// 按地址索引的键以公钥哈希的长度和公钥哈希开头, 同一地址的记录相邻  
+fn address_prefix(pub_key_hash: &[u8]) -> Vec<u8> {  
+    let mut prefix = vec![pub_key_hash.len() as u8];  
+    prefix.extend_from_slice(pub_key_hash);  
+    prefix  
+}  
+
+fn address_key(pub_key_hash: &[u8], suffix: &[u8]) -> Vec<u8> {  
+    let mut key = address_prefix(pub_key_hash);  
+    key.extend_from_slice(suffix);  
+    key  
+}  
+
+// addressHistory 键的后半部分: 8 字节大端的区块高度后接交易 id  
+fn history_suffix(height: u64, tx_id: &[u8]) -> Vec<u8> {  
+    let mut suffix = height.to_be_bytes().to_vec();  
+    suffix.extend_from_slice(tx_id);  
+    suffix  
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ChainConfig, Network};
+    use crate::miner::Miner;
+    use crate::transactions::TXInput;
+    use crate::wallet::Wallet;
+
+    // revert 撤销一个花费了 coinbase 输出的区块后, UTXO 集, 地址索引和历史都回到接入它之前的状态
+    #[test]
+    fn revert_restores_state_before_update() {
+        let datadir = std::env::temp_dir().join(format!("utxoset-revert-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&datadir);
+        let config = ChainConfig::new(Network::Regtest, &datadir);
+        std::fs::create_dir_all(config.chain_dir()).unwrap();
+
+        let wallet = Wallet::new().unwrap();
+        let address = wallet.get_address(&config);
+        let pub_key_hash = functions::publicKey_to_hash(&wallet.public_key);
+        let mut bc = BlockChain::create(&address, &config).unwrap();
+        let genesis_tx = bc.get_block(&bc.tip).unwrap().unwrap().transactions[0].clone();
+        for _ in 0..config.coinbase_maturity {
+            let coinbase = Transaction::new_coinbase_transcation(&address, "Reward", bc.next_height().unwrap(), 0, &config).unwrap();
+            bc.MineBlock(vec![coinbase], &Miner::default()).unwrap();
+        }
+
+        let utxo_set = UTXOSet { blockchain: bc.clone() };
+        let utxos_before = utxo_set.utxo_map().unwrap();
+        let history_before = utxo_set.address_history(&pub_key_hash).unwrap();
+        let tip_before = bc.tip.clone();
+
+        let mut spend = Transaction {
+            id: Vec::new(),
+            inputs: vec![TXInput {
+                transcation_id: genesis_tx.id.clone(),
+                vout: 0,
+                Signature: Vec::new(),
+                PubKey: wallet.public_key.clone(),
+            }],
+            outputs: vec![TXOutput::newTXOutput(genesis_tx.outputs[0].value - 1, &address).unwrap()],
+        };
+        spend.id = spend.set_id();
+        bc.sign_transaction(&mut spend, &wallet).unwrap();
+        let coinbase = Transaction::new_coinbase_transcation(&address, "Reward", bc.next_height().unwrap(), 1, &config).unwrap();
+        let block = bc.MineBlock(vec![coinbase, spend], &Miner::default()).unwrap();
+        assert_ne!(utxo_set.utxo_map().unwrap(), utxos_before);
+
+        assert!(utxo_set.revert(&block).unwrap());
+        assert_eq!(utxo_set.utxo_map().unwrap(), utxos_before);
+        assert_eq!(utxo_set.address_history(&pub_key_hash).unwrap(), history_before);
+        assert!(utxo_set.check_address_index().unwrap());
+        assert_eq!(bc.db.get(UTXO_TIP_KEY).unwrap().unwrap().as_ref(), tip_before.as_slice());
+        // 撤销数据已经用掉, 再次撤销时不修改 UTXO 集
+        assert!(!utxo_set.revert(&block).unwrap());
+        assert_eq!(utxo_set.utxo_map().unwrap(), utxos_before);
+
+        drop(utxo_set);
+        drop(bc);
+        let _ = std::fs::remove_dir_all(&datadir);
+    }
 }
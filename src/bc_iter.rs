@@ -1,5 +1,6 @@
-use sled::{Db};
+use sled::Db;
 use crate::block::Block;
+use crate::error::Result;
 pub struct BlockchainIterator<'a> {  
     pub current_hash: Vec<u8>,  
     pub db: &'a Db,  
@@ -13,17 +14,35 @@ impl<'a> BlockchainIterator<'a> {
             db,  
         }  
     }  
-    pub fn next(&mut self) -> Option<Block> {  
-        let block_tree = self.db.open_tree("blocks").unwrap();  
-        if let Some(block_bytes) = block_tree.get(&self.current_hash).expect("Failed to get block") {  
+
+    fn read_block(&self) -> Result<Option<Block>> {  
+        let block_tree = self.db.open_tree("blocks")?;  
+        match block_tree.get(&self.current_hash)? {  
             // 反序列化区块  
-            let block: Block = bincode::deserialize(&block_bytes).expect("Failed to deserialize block");  
-            // println!("Value: {:?}", block);  
-            self.current_hash = block.previous_block_hash.clone();  
-            Some(block)  
-        } else { 
-            println!("No more blocks"); 
-            None // 没有更多区块  
+            Some(block_bytes) => Ok(Some(Block::deserialize_block(&block_bytes)?)),  
+            None => Ok(None),  
+        }  
+    }  
+}  
+
+// 数据库读取失败时产生一个 Err, 之后迭代结束  
+impl Iterator for BlockchainIterator<'_> {  
+    type Item = Result<Block>;  
+
+    fn next(&mut self) -> Option<Result<Block>> {  
+        if self.current_hash.is_empty() {  
+            return None; // 已经越过创世区块  
+        }  
+        match self.read_block() {  
+            Ok(Some(block)) => {  
+                self.current_hash = block.header.prev_block_hash.clone();  
+                Some(Ok(block))  
+            }  
+            Ok(None) => None, // 没有更多区块  
+            Err(e) => {  
+                self.current_hash = Vec::new();  
+                Some(Err(e))  
+            }  
         }  
     }  
-}  
\ No newline at end of file
+}
@@ -1,64 +1,78 @@
-use sha3::{Sha3_256, Digest};
-use sled::transaction;
-use std::{result, time::{SystemTime, UNIX_EPOCH}};
-use serde::{Serialize, Deserialize}; 
-use crate::proof_of_work::ProofOfWork;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use crate::block_header::BlockHeader;
 use crate::transactions::Transaction;
 use crate::merkle_tree::MerkleTree;
+use crate::BLOCK_VERSION;
+use crate::error::Result;
 
 
-#[derive(Serialize, Deserialize, Debug)]  
-pub struct Block {  
-    pub timestamp: u64,  
-    pub previous_block_hash: Vec<u8>,  
-    pub hash: Vec<u8>,  
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Block {
+    pub header: BlockHeader,
+    pub hash: Vec<u8>,
     pub transactions: Vec<Transaction>,
-
-    pub nonce: u32,
-}  
+}
 
 impl Block {
-    fn hash_transactions(&self) -> Vec<u8> {  
-        let mut tx_serialized: Vec<Vec<u8>> = Vec::new();  
+    pub fn hash_transactions(transactions: &[Transaction]) -> Vec<u8> {
+        let mut tx_serialized: Vec<Vec<u8>> = Vec::new();
 
-        for tx in &self.transactions {  
-            tx_serialized.push(tx.serialize());  
-        }  
+        for tx in transactions {
+            tx_serialized.push(tx.serialize());
+        }
 
         let mtree = MerkleTree::new(tx_serialized);
 
-        mtree.root_node.unwrap().data
-    }  
+        // 没有交易时 merkle root 为空
+        mtree.root_node.map(|node| node.data).unwrap_or_default()
+    }
 
-    pub fn new(transactions: Vec<Transaction>, prev_block_hash: Vec<u8>) -> Self {
+    // 还没有挖矿的区块, nonce 和哈希由 Miner::mine 填入
+    pub fn new(transactions: Vec<Transaction>, prev_block_hash: Vec<u8>, bits: u32) -> Self {
         let timestamp = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .expect("Time went backwards")
         .as_secs();
 
-        let mut block = Block {
+        let header = BlockHeader {
+            version: BLOCK_VERSION,
+            prev_block_hash,
+            merkle_root: Self::hash_transactions(&transactions),
             timestamp,
-            previous_block_hash: prev_block_hash.clone(),
-            transactions: transactions.clone(),
-            hash: Vec::new(),
+            bits,
             nonce: 0,
         };
-        let pow = ProofOfWork::new(&block);
-
-        (block.nonce, block.hash) = pow.run();
-        
-        return block;
+        Block {
+            header,
+            transactions,
+            hash: Vec::new(),
+        }
+    }
+    // coinbase 的 extra-nonce 加一并重新计算 merkle root, 区块中没有可修改的 coinbase 时返回 false
+    pub fn increment_extra_nonce(&mut self) -> bool {
+        let coinbase = match self.transactions.iter_mut().find(|tx| tx.is_coinbase()) {
+            Some(coinbase) => coinbase,
+            None => return false,
+        };
+        let extra_nonce = match coinbase.extra_nonce() {
+            Some(extra_nonce) => extra_nonce,
+            None => return false,
+        };
+        coinbase.set_extra_nonce(extra_nonce.wrapping_add(1));
+        self.header.merkle_root = Self::hash_transactions(&self.transactions);
+        true
     }
+
     //序列化
-    pub fn serialize(&self) -> Vec<u8> {  
-        bincode::serialize(&self).unwrap()  
-    }  
-    pub fn deserialize_block(d: &[u8]) -> Block {  
-        let block: Block = bincode::deserialize(d).expect("Failed to deserialize block");  
-        block  
-    }  
-    pub fn serialize_transactions(&self) -> Vec<u8> {  
-        bincode::serialize(&self.transactions).unwrap() 
-    }  
+    pub fn serialize(&self) -> Vec<u8> {
+        bincode::serialize(&self).unwrap()
+    }
+    pub fn deserialize_block(d: &[u8]) -> Result<Block> {
+        Ok(bincode::deserialize(d)?)
+    }
+    pub fn serialize_transactions(&self) -> Vec<u8> {
+        bincode::serialize(&self.transactions).unwrap()
+    }
 
-}
\ No newline at end of file
+}
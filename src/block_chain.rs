@@ -1,94 +1,620 @@
+use crate::{block::Block, MAX_FUTURE_BLOCK_TIME, MAX_TARGET_BITS, RETARGET_INTERVAL};
+use crate::config::ChainConfig;
 use crate::functions;
-use crate::{block::Block, DB_FILE};
+use crate::block_header::BlockHeader;
+use crate::error::{Error, Result};
+use crate::proof_of_work::{self, ProofOfWork};
 use crate::bc_iter::BlockchainIterator;
-use std::clone;
-use std::collections::HashMap;
-use crate::transactions::{Transaction, TXOutput, TXOutputs};
-use ring::signature::EcdsaKeyPair;
-use sled::{transaction, Db, IVec};  
-use serde::{Serialize, Deserialize}; 
+use crate::miner::Miner;
+use std::collections::{HashMap, HashSet};
+use crate::transactions::{TXOutput, Transaction, TxVerdict};
+use crate::UTXOset::{outpoint_key, UTXOEntry, UTXOSet, UTXO_TIP_KEY};
+use num_bigint::BigUint;
+use crate::wallet::Wallet;
+use serde::{Serialize, Deserialize};
+use sled::Db;  
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 区块和索引的存储格式版本号, 保存在数据库的 CHAIN_FORMAT_KEY 下  
+// 没有这个键或版本不同的数据库 (例如旧版本按整个区块计算哈希写入的) 无法读取  
+const CHAIN_FORMAT_KEY: &str = "chain_format";  
+const CHAIN_FORMAT_VERSION: u8 = 1;  
+
+// 每个已保存区块 (包括侧链区块) 的高度和从创世区块累计的工作量  
+#[derive(Serialize, Deserialize, Debug, Clone)]  
+pub struct BlockIndex {  
+    pub height: u64,  
+    // 累计工作量, BigUint 的大端字节  
+    pub chain_work: Vec<u8>,  
+}  
+
+impl BlockIndex {  
+    pub fn work(&self) -> BigUint {  
+        BigUint::from_bytes_be(&self.chain_work)  
+    }  
+}  
+
+// 主链切换时需要断开和接入的区块, 均按执行顺序排列  
+// 单纯延长主链时 disconnected 为空, connected 只有新区块  
+#[derive(Debug)]  
+pub struct ChainSwitch {  
+    pub disconnected: Vec<Block>,  
+    pub connected: Vec<Block>,  
+}  
 
 #[derive(Debug, Clone)] 
 pub struct BlockChain {
     pub tip: Vec<u8>, 
     pub db: Db,
+    pub config: ChainConfig,
     // pub blocks: Vec<Block>,
 }
 
 impl BlockChain {
 
-    pub fn new_blockchain(address: &String) -> BlockChain {  
-        let db = sled::open(DB_FILE).expect("Failed to open database");  
-        let mut tip = vec![];  
+    // 在数据目录中创建新的区块链并从创世区块建立 UTXO 集, 已有区块链时拒绝覆盖  
+    pub fn create(address: &str, config: &ChainConfig) -> Result<BlockChain> {  
+        let db = sled::open(config.db_path())?;  
+        if db.contains_key("tip")? {  
+            Self::check_format(&db, config)?;  
+            return Err(Error::BlockchainExists(config.db_path()));  
+        }  
+
+        let cbtx = Transaction::new_coinbase_transcation(address, &config.genesis_data, 0, 0, config)?;  
+        let genesis = Self::NewGenesisBlock(cbtx, config.target_bits, &Miner::default())?;  
+
+        Self::store_block(&db, &genesis)?;  
+        Self::store_index(&db, &genesis.hash, &BlockIndex {  
+            height: 0,  
+            chain_work: block_work(genesis.header.bits).to_bytes_be(),  
+        })?;  
+        db.insert("tip", genesis.hash.clone())?;  
+        db.insert(CHAIN_FORMAT_KEY, &[CHAIN_FORMAT_VERSION])?;  
+        let tip = genesis.hash;  
+
+        let bc = BlockChain { tip, db, config: config.clone() };  
+        UTXOSet { blockchain: bc.clone() }.reindex()?;  
+        bc.db.flush()?;  
+        Ok(bc)
+    }  
+
+    // 已有区块链的数据库必须是当前的存储格式  
+    fn check_format(db: &Db, config: &ChainConfig) -> Result<()> {  
+        if db.get(CHAIN_FORMAT_KEY)?.as_deref() != Some(&[CHAIN_FORMAT_VERSION][..]) {  
+            return Err(Error::IncompatibleDatabase(config.db_path()));  
+        }  
+        Ok(())
+    }  
+
+    // 打开数据目录中已有的区块链, 从数据库读取主链 tip, 并检查 UTXO 集是否停在 tip  
+    pub fn open(config: &ChainConfig) -> Result<BlockChain> {  
+        // 不存在时不创建空数据库  
+        if !config.db_path().exists() {  
+            return Err(Error::NoBlockchain);  
+        }  
+        let db = sled::open(config.db_path())?;  
+        // 接入创世区块后还没来得及保存 tip 时, 以 UTXO 集所在的区块为准  
+        let tip = match db.get("tip")? {  
+            Some(tip) => tip.to_vec(),  
+            None => db.get(UTXO_TIP_KEY)?.ok_or(Error::NoBlockchain)?.to_vec(),  
+        };  
+        Self::check_format(&db, config)?;  
+
+        let mut bc = BlockChain { tip, db, config: config.clone() };  
+        bc.recover_utxo_tip()?;  
+        Ok(bc)
+    }  
+
+    // 上次在更新 UTXO 集和保存 tip 之间中断时, 两者指向不同的区块, 各自都是一致的状态  
+    // UTXO 集所在的区块工作量不少于 tip 时直接以它为 tip; 否则从它重新接入到 tip, tip 的分支无效时停在 UTXO 集所在的区块  
+    // 没有记录 UTXO_TIP_KEY 的旧数据库由 UTXOSet::migrate 重建  
+    fn recover_utxo_tip(&mut self) -> Result<()> {  
+        let utxo_tip = match self.db.get(UTXO_TIP_KEY)? {  
+            Some(hash) if hash.as_ref() != self.tip.as_slice() => hash.to_vec(),  
+            _ => return Ok(()),  
+        };  
+        let (utxo_index, tip_index) = match (self.get_index(&utxo_tip)?, self.get_index(&self.tip)?) {  
+            (Some(utxo_index), Some(tip_index)) => (utxo_index, tip_index),  
+            _ => return Ok(()),  
+        };  
+
+        let target = std::mem::replace(&mut self.tip, utxo_tip);  
+        if tip_index.work() > utxo_index.work() {  
+            let switch = self.find_switch(&target)?;  
+            let mut utxo_set = UTXOSet {  
+                blockchain: self.clone(),  
+            };  
+            match utxo_set.apply(&switch) {  
+                Ok(()) => self.tip = target,  
+                Err(e) if e.is_storage() => return Err(e),  
+                Err(_) => {}  
+            }  
+        }  
+        self.db.insert("tip", self.tip.clone())?;  
+        Ok(())
+    }  
+
+    // 打开已有的区块链; 没有时打开一个空的数据库, tip 为空, 等待从其他节点收到创世区块  
+    pub fn open_or_init(config: &ChainConfig) -> Result<BlockChain> {  
+        match Self::open(config) {  
+            Err(Error::NoBlockchain) => {  
+                let db = sled::open(config.db_path())?;  
+                db.insert(CHAIN_FORMAT_KEY, &[CHAIN_FORMAT_VERSION])?;  
+                Ok(BlockChain { tip: Vec::new(), db, config: config.clone() })
+            }  
+            result => result,  
+        }  
+    }  
+
+    // 区块体存入 blocks, 区块头单独存入 headers, 便于只读取/转发区块头  
+    fn store_block(db: &Db, block: &Block) -> Result<()> {  
+        let blocks_tree = db.open_tree("blocks")?;  
+        blocks_tree.insert(block.hash.clone(), block.serialize())?;  
+        let headers_tree = db.open_tree("headers")?;  
+        headers_tree.insert(block.hash.clone(), block.header.serialize())?;  
+        Ok(())
+    }  
+
+    fn store_index(db: &Db, hash: &[u8], index: &BlockIndex) -> Result<()> {  
+        let index_tree = db.open_tree("block_index")?;  
+        let encoded = bincode::serialize(index)?;  
+        index_tree.insert(hash, encoded)?;  
+        Ok(())
+    }  
 
-        let blocks_bucket = db.open_tree("blocks").expect("Failed to open blocks tree");  
+    // 当前主链 tip 的高度  
+    pub fn height(&self) -> Result<u64> {  
+        Ok(self.get_index(&self.tip)?.map(|index| index.height).unwrap_or(0))
+    }  
 
-        // if blocks_bucket.is_empty() {  
-            let cbtx = Transaction::new_coinbase_transcation(address, &"Genesis Block".to_string());  
-            let genesis = Self::NewGenesisBlock(cbtx);  
-            
-            blocks_bucket.insert(genesis.hash.clone(), genesis.serialize()).expect("Failed to insert genesis block");  
-            db.insert("tip", genesis.hash.clone()).expect("Failed to insert tip");  
-            tip = genesis.hash;  
-        // } else {  
-        //     tip = db.get("tip").expect("Failed to get tip").unwrap().to_vec();
-        // } 
- 
-        BlockChain { tip, db }  
+    // 下一个接在 tip 之后的区块的高度, 还没有创世区块时为 0  
+    pub fn next_height(&self) -> Result<u64> {  
+        if self.tip.is_empty() {  
+            return Ok(0);  
+        }  
+        Ok(self.height()? + 1)
     }  
 
-    pub fn NewGenesisBlock(coinbase: Transaction) -> Block {  
+    // 沿主链统计实际新发行的币: 每个区块 coinbase 的金额减去它收取的手续费  
+    pub fn issued_supply(&self) -> Result<i64> {  
+        let mut known_txs: HashMap<Vec<u8>, Transaction> = HashMap::new();  
+        let mut issued: i64 = 0;  
+        for block in &self.main_chain_blocks()? {  
+            for tx in &block.transactions {  
+                issued += tx.outputs.iter().map(|out| out.value as i64).sum::<i64>();  
+                if !tx.is_coinbase() {  
+                    for vin in &tx.inputs {  
+                        if let Some(prev_tx) = known_txs.get(&vin.transcation_id) {  
+                            issued -= prev_tx.outputs[vin.vout].value as i64;  
+                        }  
+                    }  
+                }  
+            }  
+            for tx in &block.transactions {  
+                known_txs.insert(tx.id.clone(), tx.clone());  
+            }  
+        }  
+        Ok(issued)
+    }  
+
+    pub fn get_index(&self, hash: &[u8]) -> Result<Option<BlockIndex>> {  
+        let index_tree = self.db.open_tree("block_index")?;  
+        match index_tree.get(hash)? {  
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),  
+            None => Ok(None),  
+        }  
+    }  
+
+    // 区块头的高度和累计工作量; 只下载了区块头的区块记录在 header_index, 已有区块体的区块记录在 block_index  
+    pub fn get_header_index(&self, hash: &[u8]) -> Result<Option<BlockIndex>> {  
+        let header_index = self.db.open_tree("header_index")?;  
+        match header_index.get(hash)? {  
+            Some(bytes) => Ok(Some(bincode::deserialize(&bytes)?)),  
+            None => self.get_index(hash),  
+        }  
+    }  
+
+    // 已验证的区块头中累计工作量最大的一个, 没有单独下载过区块头时就是 tip  
+    pub fn best_header(&self) -> Result<Vec<u8>> {  
+        Ok(self.db.get("best_header")?.map(|hash| hash.to_vec()).unwrap_or_else(|| self.tip.clone()))
+    }  
+
+    // 交易无效的区块和它的所有后代记录在 invalid 中, 不再接受它们和以它们为父区块的区块头与区块  
+    // best_header 改为其余区块头中累计工作量最大的一个  
+    pub fn mark_invalid(&self, hash: &[u8]) -> Result<()> {  
+        let mut children: HashMap<Vec<u8>, Vec<Vec<u8>>> = HashMap::new();  
+        for item in self.db.open_tree("headers")?.iter() {  
+            let (child, bytes) = item?;  
+            let header = BlockHeader::deserialize_header(&bytes)?;  
+            children.entry(header.prev_block_hash).or_default().push(child.to_vec());  
+        }  
+        let invalid = self.db.open_tree("invalid")?;  
+        let mut queue = vec![hash.to_vec()];  
+        while let Some(hash) = queue.pop() {  
+            if let Some(next) = children.remove(&hash) {  
+                queue.extend(next);  
+            }  
+            invalid.insert(hash, vec![])?;  
+        }  
+
+        let mut best: Option<(BigUint, Vec<u8>)> = None;  
+        for tree in ["header_index", "block_index"] {  
+            for item in self.db.open_tree(tree)?.iter() {  
+                let (hash, bytes) = item?;  
+                if invalid.contains_key(&hash)? {  
+                    continue;  
+                }  
+                let work = bincode::deserialize::<BlockIndex>(&bytes)?.work();  
+                if best.as_ref().is_none_or(|(best_work, _)| work > *best_work) {  
+                    best = Some((work, hash.to_vec()));  
+                }  
+            }  
+        }  
+        match best {  
+            Some((_, hash)) => self.db.insert("best_header", hash)?,  
+            None => self.db.remove("best_header")?,  
+        };  
+        Ok(())
+    }  
+
+    pub fn is_invalid(&self, hash: &[u8]) -> Result<bool> {  
+        Ok(self.db.open_tree("invalid")?.contains_key(hash)?)
+    }  
+
+    fn update_best_header(&self, hash: &[u8], index: &BlockIndex) -> Result<()> {  
+        let best = self.best_header()?;  
+        let better = match self.get_header_index(&best)? {  
+            Some(best_index) => index.work() > best_index.work(),  
+            None => true,  
+        };  
+        if better {  
+            self.db.insert("best_header", hash)?;  
+        }  
+        Ok(())
+    }  
+
+    // 只验证并保存区块头 (与父区块头相连, 难度和工作量证明正确), 区块体之后再下载  
+    // 返回区块头的哈希; 父区块头未知时返回 BlockNotFound  
+    pub fn add_header(&mut self, header: &BlockHeader) -> Result<Vec<u8>> {  
+        let prev_index = if header.prev_block_hash.is_empty() {  
+            None  
+        } else {  
+            Some(self.get_header_index(&header.prev_block_hash)?  
+                .ok_or_else(|| Error::BlockNotFound(header.prev_block_hash.clone()))?)  
+        };  
+        let bits = self.check_bits(header)?;  
+        let pow = ProofOfWork::new(header);  
+        let hash = pow.hash();  
+        if self.is_invalid(&hash)? || self.is_invalid(&header.prev_block_hash)? {  
+            return Err(Error::InvalidBlock("header is on a chain with an invalid block".to_string()));  
+        }  
+        if self.get_header_index(&hash)?.is_some() {  
+            return Ok(hash);  
+        }  
+        self.check_timestamp(header)?;  
+
+        let index = match prev_index {  
+            Some(prev_index) => BlockIndex {  
+                height: prev_index.height + 1,  
+                chain_work: (prev_index.work() + block_work(header.bits)).to_bytes_be(),  
+            },  
+            None if !self.best_header()?.is_empty() => {  
+                return Err(Error::InvalidBlock("header is a different genesis block".to_string()));  
+            }  
+            None => BlockIndex {  
+                height: 0,  
+                chain_work: block_work(header.bits).to_bytes_be(),  
+            },  
+        };  
+        if !pow.validate(bits) {  
+            return Err(Error::InvalidBlock("invalid proof of work".to_string()));  
+        }  
+
+        self.db.open_tree("headers")?.insert(&hash, header.serialize())?;  
+        self.db.open_tree("header_index")?.insert(&hash, bincode::serialize(&index)?)?;  
+        self.update_best_header(&hash, &index)?;  
+        Ok(hash)
+    }  
+
+    // best_header 所在的区块头链上还没有区块体的区块, 按高度排列, 需要依次下载并接入  
+    pub fn missing_bodies(&self) -> Result<Vec<Vec<u8>>> {  
+        let mut missing = Vec::new();  
+        let mut hash = self.best_header()?;  
+        while !hash.is_empty() && self.get_index(&hash)?.is_none() {  
+            let header = self.get_header(&hash)?.ok_or_else(|| Error::BlockNotFound(hash.clone()))?;  
+            missing.push(hash);  
+            hash = header.prev_block_hash;  
+        }  
+        missing.reverse();  
+        Ok(missing)
+    }  
+
+    // 从创世区块到 tip 的主链区块, 按高度排列  
+    pub fn main_chain_blocks(&self) -> Result<Vec<Block>> {  
+        let mut blocks: Vec<Block> = Vec::new();  
+        for block in self.iterator() {  
+            let block = block?;  
+            let is_genesis = block.header.prev_block_hash.is_empty();  
+            blocks.push(block);  
+            if is_genesis {  
+                break;  
+            }  
+        }  
+        blocks.reverse();  
+        Ok(blocks)
+    }  
+
+    pub fn get_block(&self, hash: &[u8]) -> Result<Option<Block>> {  
+        let blocks_tree = self.db.open_tree("blocks")?;  
+        match blocks_tree.get(hash)? {  
+            Some(bytes) => Ok(Some(Block::deserialize_block(&bytes)?)),  
+            None => Ok(None),  
+        }  
+    }  
+
+    pub fn get_header(&self, hash: &[u8]) -> Result<Option<BlockHeader>> {  
+        let headers_tree = self.db.open_tree("headers")?;  
+        match headers_tree.get(hash)? {  
+            Some(bytes) => Ok(Some(BlockHeader::deserialize_header(&bytes)?)),  
+            None => Ok(None),  
+        }  
+    }  
+
+    pub fn NewGenesisBlock(coinbase: Transaction, bits: u32, miner: &Miner) -> Result<Block> {  
         let transactions = vec![coinbase];  
-        Block::new(transactions, vec![]) // Pass an empty hash for the genesis block  
+        let mut genesis = Block::new(transactions, vec![], bits); // Pass an empty hash for the genesis block  
+        miner.mine(&mut genesis)?;  
+        Ok(genesis)
     }
 
-    pub fn MineBlock(&mut self, transactions: Vec<Transaction>) -> Block {  
-        for tx in &transactions {  
-            if !self.verify_transaction(tx) {  
-                panic!("ERROR: Invalid transaction");  
+    // 用 miner 为接在 tip 之后的一组交易挖出区块并加入主链 (同时更新 UTXO 集), 被取消时返回 MiningCancelled  
+    pub fn MineBlock(&mut self, transactions: Vec<Transaction>, miner: &Miner) -> Result<Block> {  
+        if self.tip.is_empty() {  
+            return Err(Error::NoBlockchain);  
+        }  
+        self.check_transactions(&transactions)?;  
+
+        let mut new_block = self.new_block(transactions)?;
+        miner.mine(&mut new_block)?;
+        self.add_block(&new_block)?;  
+
+        Ok(new_block)
+
+    } 
+
+    // 接在当前 tip 之后, 还没有挖矿的区块: 使用期望的难度, 时间戳不早于 tip  
+    pub fn new_block(&self, transactions: Vec<Transaction>) -> Result<Block> {  
+        let bits = self.expected_bits(&self.tip)?;  
+        let mut block = Block::new(transactions, self.tip.clone(), bits);  
+        if let Some(tip) = self.get_header(&self.tip)? {  
+            block.header.timestamp = block.header.timestamp.max(tip.timestamp);  
+        }  
+        Ok(block)
+    }  
+
+    // 检查准备接在当前 tip 之后的一组交易: 恰好有一笔 coinbase, 每笔交易都有效,  
+    // 花费的输出在主链上未被花费且不在组内重复花费, coinbase 领取的金额不超过下一个高度的补贴加上手续费  
+    pub fn check_transactions(&self, transactions: &[Transaction]) -> Result<()> {  
+        let coinbases = transactions.iter().filter(|tx| tx.is_coinbase()).count();  
+        if coinbases != 1 {  
+            return Err(Error::InvalidBlock(format!("expected 1 coinbase transaction, found {}", coinbases)));  
+        }  
+        let utxo_bucket = self.db.open_tree("utxoBucket")?;  
+        let mut spent = HashSet::new();  
+        let mut fees: i64 = 0;  
+        for tx in transactions {  
+            let verdict = self.verify_transaction(tx)?;  
+            if !verdict.is_valid() {  
+                return Err(verdict.into());  
+            }  
+            if !tx.is_coinbase() {  
+                for (in_id, vin) in tx.inputs.iter().enumerate() {  
+                    let key = outpoint_key(&vin.transcation_id, vin.vout);  
+                    if !utxo_bucket.contains_key(&key)? || !spent.insert(key) {  
+                        return Err(TxVerdict::AlreadySpent { input: in_id }.into());  
+                    }  
+                }  
+            }  
+            fees += tx.fee(&self.prev_transactions(tx)?);  
+        }  
+        let coinbase_outputs: Vec<&TXOutput> = transactions.iter()  
+            .filter(|tx| tx.is_coinbase())  
+            .flat_map(|tx| tx.outputs.iter())  
+            .collect();  
+        // 负的输出会抵消其他输出, 让总额看起来不超过补贴  
+        if coinbase_outputs.iter().any(|out| out.value < 0) {  
+            return Err(Error::InvalidBlock("coinbase has a negative output".to_string()));  
+        }  
+        let reward: i64 = coinbase_outputs.iter().map(|out| out.value as i64).sum();  
+        let available = functions::block_subsidy(&self.config, self.next_height()?) as i64 + fees;  
+        if reward > available {  
+            return Err(Error::InvalidBlock(format!(
+                "coinbase claims {} but only {} is available", reward, available
+            )));  
+        }  
+        Ok(())
+    }  
+
+    // 保存一个区块 (可以来自任意分支), 若它所在的分支累计工作量超过当前主链则切换主链  
+    // 切换时新分支上的区块逐个按接入时的 UTXO 状态检查交易并更新 UTXO 集, 有无效区块时保留原来的主链并返回错误,  
+    // 无效的区块和它的后代由 mark_invalid 记录  
+    // 返回主链的变化; 区块只是被存为侧链时返回 None  
+    pub fn add_block(&mut self, block: &Block) -> Result<Option<ChainSwitch>> {  
+        if self.get_index(&block.hash)?.is_some() {  
+            return Ok(None);  
+        }  
+        if self.is_invalid(&block.hash)? || self.is_invalid(&block.header.prev_block_hash)? {  
+            return Err(Error::InvalidBlock("block is on a chain with an invalid block".to_string()));  
+        }  
+        let is_genesis = block.header.prev_block_hash.is_empty();  
+        if is_genesis && !self.tip.is_empty() {  
+            return Err(Error::InvalidBlock("block is a different genesis block".to_string()));  
+        }  
+        let prev_index = if is_genesis {  
+            None  
+        } else {  
+            Some(self.get_index(&block.header.prev_block_hash)?  
+                .ok_or_else(|| Error::BlockNotFound(block.header.prev_block_hash.clone()))?)  
+        };  
+
+        let bits = self.check_bits(&block.header)?;  
+        let pow = ProofOfWork::new(&block.header);  
+        if pow.hash() != block.hash {  
+            return Err(Error::InvalidBlock("block hash does not match its header".to_string()));  
+        }  
+        if !pow.validate(bits) {  
+            return Err(Error::InvalidBlock("invalid proof of work".to_string()));  
+        }  
+        self.check_timestamp(&block.header)?;  
+        if block.header.merkle_root != Block::hash_transactions(&block.transactions) {  
+            return Err(Error::InvalidBlock("merkle root does not match the transactions".to_string()));  
+        }  
+
+        // 空的区块链 (见 open_or_init) 接受从其他节点收到的创世区块  
+        let index = match prev_index {  
+            Some(prev_index) => BlockIndex {  
+                height: prev_index.height + 1,  
+                chain_work: (prev_index.work() + block_work(block.header.bits)).to_bytes_be(),  
+            },  
+            None => BlockIndex {  
+                height: 0,  
+                chain_work: block_work(block.header.bits).to_bytes_be(),  
+            },  
+        };  
+        Self::store_block(&self.db, block)?;  
+        Self::store_index(&self.db, &block.hash, &index)?;  
+        self.update_best_header(&block.hash, &index)?;  
+
+        if !self.tip.is_empty() {  
+            let tip_index = self.get_index(&self.tip)?  
+                .ok_or_else(|| Error::BlockNotFound(self.tip.clone()))?;  
+            if index.work() <= tip_index.work() {  
+                // 工作量不超过当前主链, 作为侧链保存  
+                return Ok(None);  
             }  
         }  
 
-        let last_hash: Vec<u8>;   
-        match self.db.get("tip") {  
-            Ok(Some(last_hash_bytes)) => {  
-                last_hash = last_hash_bytes.to_vec();
-                // println!("Last hash/tip: {:?}", last_hash);  
+        let switch = self.find_switch(&block.hash)?;  
+        let mut utxo_set = UTXOSet {  
+            blockchain: self.clone(),  
+        };  
+        utxo_set.apply(&switch)?;  
+        self.db.insert("tip", block.hash.clone())?;  
+        self.tip = block.hash.clone();  
+        Ok(Some(switch))  
+    }  
+
+    // 区块时间戳不早于父区块, 也不超过本地时间加上 MAX_FUTURE_BLOCK_TIME, 与 verifychain 的规则相同  
+    fn check_timestamp(&self, header: &BlockHeader) -> Result<()> {  
+        let now = SystemTime::now()  
+            .duration_since(UNIX_EPOCH)  
+            .expect("Time went backwards")  
+            .as_secs();  
+        if header.timestamp > now + MAX_FUTURE_BLOCK_TIME {  
+            return Err(Error::InvalidBlock("timestamp is too far in the future".to_string()));  
+        }  
+        if !header.prev_block_hash.is_empty() {  
+            let parent = self.get_header(&header.prev_block_hash)?  
+                .ok_or_else(|| Error::BlockNotFound(header.prev_block_hash.clone()))?;  
+            if header.timestamp < parent.timestamp {  
+                return Err(Error::InvalidBlock("timestamp is earlier than the parent block".to_string()));  
             }  
-            Ok(None) => {  
-                eprintln!("Warning: Last hash not found. Creating a new genesis block.");  
-                last_hash = vec![0; 32]; 
+        }  
+        Ok(())
+    }  
+
+    // 区块头的 bits 必须等于链在该位置期望的难度, 返回期望的 bits  
+    // 在构造工作量证明的目标值和计算工作量之前检查, 不使用对方任意给出的 bits  
+    fn check_bits(&self, header: &BlockHeader) -> Result<u32> {  
+        let expected = self.expected_bits(&header.prev_block_hash)?;  
+        if header.bits != expected || header.bits > MAX_TARGET_BITS {  
+            return Err(Error::InvalidBlock(format!("bits {} do not match the expected {}", header.bits, expected)));  
+        }  
+        Ok(expected)
+    }  
+
+    // 找到当前主链与 new_tip 所在分支的分叉点, 列出切换时需要断开和接入的区块  
+    fn find_switch(&self, new_tip: &[u8]) -> Result<ChainSwitch> {  
+        if self.tip.is_empty() {  
+            // 还没有主链, 接入 new_tip 所在的整条链  
+            let mut connected = Vec::new();  
+            let mut hash = new_tip.to_vec();  
+            while !hash.is_empty() {  
+                let block = self.get_block(&hash)?.ok_or_else(|| Error::BlockNotFound(hash.clone()))?;  
+                hash = block.header.prev_block_hash.clone();  
+                connected.push(block);  
             }  
-            Err(e) => {  
-                panic!("Failed to get the last hash from the database: {}", e);  
+            connected.reverse();  
+            return Ok(ChainSwitch { disconnected: Vec::new(), connected });  
+        }  
+        let mut old_hash = self.tip.clone();  
+        let mut new_hash = new_tip.to_vec();  
+        let mut old_index = self.get_index(&old_hash)?.ok_or_else(|| Error::BlockNotFound(old_hash.clone()))?;  
+        let mut new_index = self.get_index(&new_hash)?.ok_or_else(|| Error::BlockNotFound(new_hash.clone()))?;  
+        let mut disconnected = Vec::new();  
+        let mut connected = Vec::new();  
+
+        while old_hash != new_hash {  
+            if old_index.height >= new_index.height {  
+                let block = self.get_block(&old_hash)?.ok_or_else(|| Error::BlockNotFound(old_hash.clone()))?;  
+                old_hash = block.header.prev_block_hash.clone();  
+                disconnected.push(block);  
+                if let Some(index) = self.get_index(&old_hash)? {  
+                    old_index = index;  
+                }  
+            } else {  
+                let block = self.get_block(&new_hash)?.ok_or_else(|| Error::BlockNotFound(new_hash.clone()))?;  
+                new_hash = block.header.prev_block_hash.clone();  
+                connected.push(block);  
+                if let Some(index) = self.get_index(&new_hash)? {  
+                    new_index = index;  
+                }  
             }  
-        }; 
+        }  
+        connected.reverse();  
 
-        // 更新数据库  
-        let new_block = Block::new(transactions, last_hash.clone());
-        let blocks_tree = self.db.open_tree("blocks").expect("Failed to open blocks tree");  
-        blocks_tree.insert(new_block.hash.clone(), new_block.serialize()).expect("Failed to insert new block");   
-        self.db.insert("tip", new_block.hash.clone()).expect("Failed to update tip");  
-        self.tip = new_block.hash.clone();
+        Ok(ChainSwitch { disconnected, connected })
+    }  
 
-        return new_block;  
+    // 计算接在 prev_hash 之后的区块应当使用的难度  
+    // 每 RETARGET_INTERVAL 个区块根据上一个周期的时间戳重新调整一次, 其余区块沿用前一个区块的 bits  
+    // 高度从区块索引读取, 调整难度时只往回读取一个周期的区块头  
+    pub fn expected_bits(&self, prev_hash: &[u8]) -> Result<u32> {  
+        if prev_hash.is_empty() {  
+            return Ok(self.config.target_bits);  
+        }  
+        let last = self.get_header(prev_hash)?.ok_or_else(|| Error::BlockNotFound(prev_hash.to_vec()))?;  
+        let prev_index = self.get_header_index(prev_hash)?.ok_or_else(|| Error::BlockNotFound(prev_hash.to_vec()))?;  
+        let height = prev_index.height + 1;  
+        if !height.is_multiple_of(RETARGET_INTERVAL as u64) {  
+            return Ok(last.bits);  
+        }  
 
-    } 
+        // 周期的第一个区块, 与 last 之间相隔 RETARGET_INTERVAL - 1 个区块  
+        let mut first = last.clone();  
+        for _ in 1..RETARGET_INTERVAL {  
+            let prev = first.prev_block_hash;  
+            first = self.get_header(&prev)?.ok_or(Error::BlockNotFound(prev))?;  
+        }  
+        let actual_timespan = last.timestamp.saturating_sub(first.timestamp);  
+        Ok(proof_of_work::retarget(last.bits, actual_timespan, self.config.target_bits))
+    }  
 
-    pub fn iterator(&self) -> BlockchainIterator {
+    pub fn iterator(&self) -> BlockchainIterator<'_> {
         BlockchainIterator::new(&self.db, self.tip.clone())  
     }
 
-    pub fn find_utxo(&self) -> HashMap<Vec<u8>, TXOutputs> {  
+    // 沿主链收集全部未花费输出, 以 (交易 id, 输出序号) 为键  
+    pub fn find_utxo(&self) -> Result<HashMap<(Vec<u8>, usize), UTXOEntry>> {  
         // println!("1 find_utxo \n");
-        let mut utxo: HashMap<Vec<u8>, TXOutputs> = HashMap::new();  
+        let mut utxo: HashMap<(Vec<u8>, usize), UTXOEntry> = HashMap::new();  
         let mut spent_txos: HashMap<Vec<u8>, Vec<usize>> = HashMap::new();  
-        let mut bci = self.iterator();  
         // println!("print chain {:?} \n", &self);
-        // println!("1 bci.next() {:?} \n", bci.next());
         
-        while let Some(block) = bci.next() {
+        for block in self.iterator() {
             // println!("1 bci.next() \n");
+            let block = block?;
+            let height = self.get_index(&block.hash)?.map(|index| index.height).unwrap_or(0);  
             for tx in &block.transactions {  
                 // println!("2 \n");
                 for (out_idx, out) in tx.outputs.iter().enumerate() {  
@@ -99,27 +625,29 @@ impl BlockChain {
                         }  
                     }  
                     // println!("tx.id {:?}", &tx.id);
-                    utxo.entry(tx.id.clone())  
-                        .or_insert_with(TXOutputs::new)  
-                        .outputs.push(out.clone());  
+                    utxo.insert((tx.id.clone(), out_idx), UTXOEntry {  
+                        output: out.clone(),  
+                        height,  
+                        is_coinbase: tx.is_coinbase(),  
+                    });  
                 }  
 
                 if !tx.is_coinbase() {  
                     for input in &tx.inputs {  
                         // let in_tx_id = hex::encode(&input.transcation_id);  
                         spent_txos.entry(input.transcation_id.clone())  
-                            .or_insert_with(Vec::new)  
-                            .push(input.vout as usize);  
+                            .or_default()  
+                            .push(input.vout);  
                     }  
                 }  
             }  
 
-            if block.previous_block_hash.is_empty() {  
+            if block.header.prev_block_hash.is_empty() {  
                 break;  
             }  
         }
         // println!("utxo {:?}", &utxo);
-        utxo  
+        Ok(utxo)
     }  
 
 
@@ -207,56 +735,419 @@ impl BlockChain {
     //     (accumulated, unspent_outputs) 
     // }  
 
-    pub fn find_transaction(&self, id: &Vec<u8>) -> Transaction {  
-        let mut iterator = self.iterator();  
-    
-        while let Some(block) = iterator.next() {  
+    pub fn find_transaction(&self, id: &Vec<u8>) -> Result<Transaction> {  
+        for block in self.iterator() {  
+            let block = block?;  
             for tx in &block.transactions {  
                 if &tx.id == id {  
-                    return tx.clone(); // Assuming Transaction implements Clone  
+                    return Ok(tx.clone()); // Assuming Transaction implements Clone  
                 }  
             }  
     
-            if block.previous_block_hash.is_empty() {  
+            if block.header.prev_block_hash.is_empty() {  
                 break;  
             }  
         }  
     
-        // Panic here if the transaction was not found.  
-        panic!("Transaction not found");  
+        Err(Error::TxNotFound(id.clone()))
     }
 
-    pub fn sign_transaction(&self, tx: &mut Transaction, keypair: &EcdsaKeyPair) {
+    // 主链上所有输出锁定过的公钥哈希, 用于判断一个地址是否被使用过  
+    pub fn used_pubkey_hashes(&self) -> Result<HashSet<Vec<u8>>> {  
+        let mut used = HashSet::new();  
+        for block in self.iterator() {  
+            let block = block?;  
+            for tx in &block.transactions {  
+                for out in &tx.outputs {  
+                    used.insert(out.PubKeyHash.clone());  
+                }  
+            }  
+        }  
+        Ok(used)  
+    }  
+
+    // 主链上全部区块的哈希, 从创世区块开始按高度排列, 只读取区块头  
+    pub fn main_chain_hashes(&self) -> Result<Vec<Vec<u8>>> {  
+        self.chain_hashes(&self.tip)  
+    }  
+
+    // 从 from 沿区块头往回直到创世区块的全部哈希, 按高度排列  
+    fn chain_hashes(&self, from: &[u8]) -> Result<Vec<Vec<u8>>> {  
+        let mut hashes = Vec::new();  
+        let mut hash = from.to_vec();  
+        while !hash.is_empty() {  
+            let header = self.get_header(&hash)?.ok_or_else(|| Error::BlockNotFound(hash.clone()))?;  
+            hashes.push(hash);  
+            hash = header.prev_block_hash;  
+        }  
+        hashes.reverse();  
+        Ok(hashes)
+    }  
+
+    // 区块定位器: 从 tip 往回, 前 10 个区块逐个列出, 之后间隔每次翻倍, 最后是创世区块  
+    // 对方据此找到双方主链的分叉点  
+    pub fn locator(&self) -> Result<Vec<Vec<u8>>> {  
+        Ok(Self::build_locator(&self.main_chain_hashes()?))
+    }  
+
+    // 以 best_header 为起点的定位器, 用于继续请求区块头  
+    pub fn header_locator(&self) -> Result<Vec<Vec<u8>>> {  
+        Ok(Self::build_locator(&self.chain_hashes(&self.best_header()?)?))
+    }  
+
+    fn build_locator(hashes: &[Vec<u8>]) -> Vec<Vec<u8>> {  
+        let mut locator = Vec::new();  
+        let mut step = 1;  
+        let mut index = hashes.len() as i64 - 1;  
+        while index > 0 {  
+            locator.push(hashes[index as usize].clone());  
+            if locator.len() >= 10 {  
+                step *= 2;  
+            }  
+            index -= step;  
+        }  
+        if let Some(genesis) = hashes.first() {  
+            locator.push(genesis.clone());  
+        }  
+        locator
+    }  
+
+    // 定位器中第一个位于本地主链上的区块之后的主链区块哈希, 最多 limit 个  
+    // 定位器里没有共同的区块时 (例如对方还没有创世区块) 从创世区块开始  
+    pub fn hashes_after(&self, locator: &[Vec<u8>], limit: usize) -> Result<Vec<Vec<u8>>> {  
+        let hashes = self.main_chain_hashes()?;  
+        let start = locator  
+            .iter()  
+            .find_map(|hash| hashes.iter().position(|h| h == hash))  
+            .map_or(0, |position| position + 1);  
+        Ok(hashes.into_iter().skip(start).take(limit).collect())
+    }  
+
+    // 与 hashes_after 相同, 返回的是区块头  
+    pub fn headers_after(&self, locator: &[Vec<u8>], limit: usize) -> Result<Vec<BlockHeader>> {  
+        let mut headers = Vec::new();  
+        for hash in self.hashes_after(locator, limit)? {  
+            headers.push(self.get_header(&hash)?.ok_or_else(|| Error::BlockNotFound(hash.clone()))?);  
+        }  
+        Ok(headers)
+    }  
+
+    // 包含该交易的主链区块的高度  
+    pub fn find_transaction_height(&self, id: &Vec<u8>) -> Result<Option<u64>> {  
+        for block in self.iterator() {  
+            let block = block?;  
+            if block.transactions.iter().any(|tx| &tx.id == id) {  
+                return Ok(self.get_index(&block.hash)?.map(|index| index.height));  
+            }  
+            if block.header.prev_block_hash.is_empty() {  
+                break;  
+            }  
+        }  
+        Ok(None)
+    }  
+
+    pub fn sign_transaction(&self, tx: &mut Transaction, wallet: &Wallet) -> Result<()> {
         // println!("before tx.id{:?} \n", tx.id);
 
         let mut prev_txs = HashMap::new();  
 
         for vin in &tx.inputs {  
-            let prev_tx = self.find_transaction(&vin.transcation_id);
+            let prev_tx = self.find_transaction(&vin.transcation_id)?;
             prev_txs.insert(prev_tx.id.clone(), prev_tx);  
         }  
         // println!("prec_txs {:?} \n", prev_txs);
         
-        tx.sign(keypair, &prev_txs); // Assuming sign method exists in Transaction  
+        tx.sign(wallet, &prev_txs) // Assuming sign method exists in Transaction  
 
         // println!("after id{:?} \n ", tx.id);
     }  
 
-    pub fn verify_transaction(&self, tx: &Transaction) -> bool {
+    // 交易本身的问题以 TxVerdict 返回, 读取数据库出错时返回 Err  
+    pub fn verify_transaction(&self, tx: &Transaction) -> Result<TxVerdict> {
         if tx.is_coinbase() {
-            return true
+            return Ok(tx.verify(&HashMap::new()))
         }
         // println!("curr tx{:?}\n", tx);  
-        let mut prev_txs = HashMap::new();  
+        let prev_txs = self.prev_transactions(tx)?;  
+        let verdict = tx.verify(&prev_txs); // Assuming verify method exists in Transaction  
+        if !verdict.is_valid() {  
+            return Ok(verdict);  
+        }  
 
-        for vin in &tx.inputs {  
-            let prev_tx = self.find_transaction(&vin.transcation_id);   
-            prev_txs.insert(prev_tx.id.clone(), prev_tx);  
+        // 交易将被打包进下一个区块, 它花费的 coinbase 输出必须已经成熟  
+        let spend_height = self.next_height()?;  
+        for (in_id, vin) in tx.inputs.iter().enumerate() {  
+            if prev_txs[&vin.transcation_id].is_coinbase() {  
+                let height = self.find_transaction_height(&vin.transcation_id)?.unwrap_or(0);  
+                if spend_height.saturating_sub(height) < self.config.coinbase_maturity {  
+                    return Ok(TxVerdict::ImmatureCoinbase { input: in_id });  
+                }  
+            }  
         }  
+        Ok(TxVerdict::Valid)
+    }
 
-        tx.verify(&prev_txs) // Assuming verify method exists in Transaction  
+    // 从主链中取出 tx 的输入所引用的交易, 找不到的交易留给 verify 报告 MissingPrevTx  
+    pub fn prev_transactions(&self, tx: &Transaction) -> Result<HashMap<Vec<u8>, Transaction>> {  
+        let mut prev_txs = HashMap::new();  
+        if tx.is_coinbase() {  
+            return Ok(prev_txs);  
+        }  
+        for vin in &tx.inputs {  
+            match self.find_transaction(&vin.transcation_id) {  
+                Ok(prev_tx) => {  
+                    prev_txs.insert(prev_tx.id.clone(), prev_tx);  
+                }  
+                Err(Error::TxNotFound(_)) => {}  
+                Err(e) => return Err(e),  
+            }  
+        }  
+        Ok(prev_txs)
     }  
 
 
 
-}
\ No newline at end of file
+}
+
+// 一个区块的工作量: 目标值为 2^(256 - bits), 期望的哈希次数约为 2^bits  
+pub fn block_work(bits: u32) -> BigUint {  
+    BigUint::from(1u8) << bits  
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::chain_verifier;
+    use crate::config::Network;
+    use std::path::PathBuf;
+
+    // 临时目录中的 regtest 区块链, 创世奖励归 wallet, 结束时删除目录
+    pub(crate) struct TestChain {
+        pub(crate) bc: BlockChain,
+        pub(crate) wallet: Wallet,
+        pub(crate) address: String,
+        datadir: PathBuf,
+    }
+
+    impl TestChain {
+        pub(crate) fn new(name: &str) -> TestChain {
+            let datadir = std::env::temp_dir().join(format!("block-chain-{}-{}", name, std::process::id()));
+            let _ = std::fs::remove_dir_all(&datadir);
+            let config = ChainConfig::new(Network::Regtest, &datadir);
+            std::fs::create_dir_all(config.chain_dir()).unwrap();
+            let wallet = Wallet::new().unwrap();
+            let address = wallet.get_address(&config);
+            let bc = BlockChain::create(&address, &config).unwrap();
+            TestChain { bc, wallet, address, datadir }
+        }
+
+        pub(crate) fn coinbase(&self, fees: i32) -> Transaction {
+            let height = self.bc.next_height().unwrap();
+            Transaction::new_coinbase_transcation(&self.address, "Reward", height, fees, &self.bc.config).unwrap()
+        }
+
+        // 把 prev 的第 vout 个输出减去 fee 后转回自己的地址
+        pub(crate) fn spend(&self, prev: &Transaction, vout: usize, fee: i32) -> Transaction {
+            let mut tx = Transaction {
+                id: Vec::new(),
+                inputs: vec![crate::transactions::TXInput {
+                    transcation_id: prev.id.clone(),
+                    vout,
+                    Signature: Vec::new(),
+                    PubKey: self.wallet.public_key.clone(),
+                }],
+                outputs: vec![TXOutput::newTXOutput(prev.outputs[vout].value - fee, &self.address).unwrap()],
+            };
+            tx.id = tx.set_id();
+            self.bc.sign_transaction(&mut tx, &self.wallet).unwrap();
+            tx
+        }
+
+        pub(crate) fn mine(&mut self, transactions: Vec<Transaction>) -> Block {
+            let fees = transactions.iter().map(|tx| tx.fee(&self.bc.prev_transactions(tx).unwrap())).sum::<i64>();
+            let mut block_txs = vec![self.coinbase(fees as i32)];
+            block_txs.extend(transactions);
+            self.bc.MineBlock(block_txs, &Miner::default()).unwrap()
+        }
+
+        // 在 parent 之后挖出一个侧链区块, 不接入区块链
+        pub(crate) fn side_block(&self, parent: &[u8], transactions: Vec<Transaction>) -> Block {
+            let mut side = self.bc.clone();
+            side.tip = parent.to_vec();
+            let fees = transactions.iter().map(|tx| tx.fee(&side.prev_transactions(tx).unwrap())).sum::<i64>();
+            let height = side.next_height().unwrap();
+            let coinbase = Transaction::new_coinbase_transcation(&self.address, "Side", height, fees as i32, &side.config).unwrap();
+            let mut block_txs = vec![coinbase];
+            block_txs.extend(transactions);
+            let mut block = side.new_block(block_txs).unwrap();
+            Miner::default().mine(&mut block).unwrap();
+            block
+        }
+
+        // 关闭后重新打开数据库, 与节点重启时一样经过 BlockChain::open
+        pub(crate) fn reopen(&mut self) {
+            let config = self.bc.config.clone();
+            self.bc.db.flush().unwrap();
+            // 先换成临时数据库, 释放 sled 对数据目录的锁
+            let placeholder = BlockChain {
+                tip: Vec::new(),
+                db: sled::Config::new().temporary(true).open().unwrap(),
+                config: config.clone(),
+            };
+            drop(std::mem::replace(&mut self.bc, placeholder));
+            self.bc = BlockChain::open(&config).unwrap();
+        }
+
+        pub(crate) fn utxo_set(&self) -> UTXOSet {
+            UTXOSet { blockchain: self.bc.clone() }
+        }
+
+        pub(crate) fn balance(&self) -> i64 {
+            self.utxo_set().find_utxos(&self.address).unwrap().iter().map(|out| out.value as i64).sum()
+        }
+    }
+
+    impl Drop for TestChain {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.datadir);
+        }
+    }
+
+    // 负的 coinbase 输出抵消多领的奖励, 总额看起来没有超过补贴
+    #[test]
+    fn coinbase_with_negative_output_is_rejected() {
+        let mut chain = TestChain::new("negative-coinbase");
+        let tip = chain.bc.tip.clone();
+        let balance = chain.balance();
+
+        let mut coinbase = chain.coinbase(0);
+        coinbase.outputs[0].value = 1_000_000;
+        coinbase.outputs.push(TXOutput::newTXOutput(-999_930, &chain.address).unwrap());
+        coinbase.id = Vec::new();
+        coinbase.id = coinbase.set_id();
+        assert_eq!(coinbase.verify(&HashMap::new()), TxVerdict::NegativeOutput { output: 1 });
+        assert!(chain.bc.MineBlock(vec![coinbase.clone()], &Miner::default()).is_err());
+
+        // 绕过 MineBlock 直接接入同样的区块
+        let mut block = chain.bc.new_block(vec![coinbase]).unwrap();
+        Miner::default().mine(&mut block).unwrap();
+        assert!(chain.bc.add_block(&block).is_err());
+
+        assert_eq!(chain.bc.tip, tip);
+        assert_eq!(chain.balance(), balance);
+        assert_eq!(chain_verifier::verify_chain(&chain.bc).unwrap(), 1);
+    }
+
+    // regtest 的 coinbase 要在产生它的区块之后再经过 coinbase_maturity 个区块才能花费
+    #[test]
+    fn immature_coinbase_spend_is_rejected_until_it_matures() {
+        let mut chain = TestChain::new("coinbase-maturity");
+        assert!(chain.bc.config.coinbase_maturity >= 2);
+        let genesis_tx = chain.bc.get_block(&chain.bc.tip).unwrap().unwrap().transactions[0].clone();
+        let pub_key_hash = functions::publicKey_to_hash(&chain.wallet.public_key);
+
+        for _ in 1..chain.bc.config.coinbase_maturity {
+            assert!(chain.utxo_set().find_spendable(&pub_key_hash).unwrap().is_empty());
+            let spend = chain.spend(&genesis_tx, 0, 1);
+            let coinbase = chain.coinbase(1);
+            match chain.bc.check_transactions(&[coinbase, spend]) {
+                Err(Error::InvalidTransaction(TxVerdict::ImmatureCoinbase { input: 0 })) => {}
+                other => panic!("immature spend accepted: {:?}", other.map_err(|e| e.to_string())),
+            }
+            chain.mine(Vec::new());
+        }
+
+        let spendable = chain.utxo_set().find_spendable(&pub_key_hash).unwrap();
+        assert_eq!(spendable.len(), 1);
+        assert_eq!(spendable[0].tx_id, genesis_tx.id);
+        let spend = chain.spend(&genesis_tx, 0, 1);
+        chain.bc.check_transactions(&[chain.coinbase(1), spend.clone()]).unwrap();
+        chain.mine(vec![spend]);
+        assert_eq!(chain_verifier::verify_chain(&chain.bc).unwrap(), chain.bc.config.coinbase_maturity as usize + 1);
+    }
+
+    // 侧链的累计工作量超过主链后切换过去, UTXO 集和余额与侧链一致
+    #[test]
+    fn side_branch_with_more_work_becomes_the_main_chain() {
+        let mut chain = TestChain::new("reorg");
+        let genesis_tx = chain.bc.get_block(&chain.bc.tip).unwrap().unwrap().transactions[0].clone();
+        let fork = chain.mine(Vec::new()).hash;
+        let balance = chain.balance();
+        let subsidy = chain.coinbase(0).outputs[0].value as i64;
+        let spend = chain.spend(&genesis_tx, 0, 10);
+        let main = chain.mine(vec![spend.clone()]);
+        assert_eq!(chain.balance(), balance + subsidy);
+
+        let side1 = chain.side_block(&fork, Vec::new());
+        assert!(chain.bc.add_block(&side1).unwrap().is_none());
+        assert_eq!(chain.bc.tip, main.hash);
+        let side2 = chain.side_block(&side1.hash, Vec::new());
+        let switch = chain.bc.add_block(&side2).unwrap().unwrap();
+        assert_eq!(switch.disconnected.iter().map(|b| b.hash.clone()).collect::<Vec<_>>(), vec![main.hash.clone()]);
+        assert_eq!(switch.connected.iter().map(|b| b.hash.clone()).collect::<Vec<_>>(), vec![side1.hash.clone(), side2.hash.clone()]);
+
+        assert_eq!(chain.bc.tip, side2.hash);
+        let utxo_set = chain.utxo_set();
+        assert!(utxo_set.get_entry(&genesis_tx.id, 0).unwrap().is_some());
+        assert!(utxo_set.get_entry(&spend.id, 0).unwrap().is_none());
+        assert_eq!(utxo_set.utxo_map().unwrap(), chain.bc.find_utxo().unwrap());
+        assert_eq!(chain.balance(), balance + subsidy * 2);
+        assert_eq!(chain_verifier::verify_chain(&chain.bc).unwrap(), 4);
+    }
+
+    // 更长的分支中有交易无效的区块: 保持原来的 tip 和 UTXO 集, 该区块和它的后代被标记为无效
+    #[test]
+    fn invalid_block_on_a_longer_branch_is_not_connected() {
+        let mut chain = TestChain::new("invalid-branch");
+        let genesis_tx = chain.bc.get_block(&chain.bc.tip).unwrap().unwrap().transactions[0].clone();
+        let fork = chain.mine(Vec::new()).hash;
+        chain.mine(Vec::new());
+        let tip = chain.bc.tip.clone();
+        let utxo_map = chain.utxo_set().utxo_map().unwrap();
+
+        // side2 再次花费 side1 已经花费的输出
+        let side1 = chain.side_block(&fork, vec![chain.spend(&genesis_tx, 0, 1)]);
+        assert!(chain.bc.add_block(&side1).unwrap().is_none());
+        let side2 = chain.side_block(&side1.hash, vec![chain.spend(&genesis_tx, 0, 2)]);
+        chain.bc.add_header(&side2.header).unwrap();
+        let mut side = chain.bc.clone();
+        side.tip = side2.hash.clone();
+        let coinbase = Transaction::new_coinbase_transcation(&chain.address, "Side", 4, 0, &side.config).unwrap();
+        let mut side3 = side.new_block(vec![coinbase]).unwrap();
+        Miner::default().mine(&mut side3).unwrap();
+        chain.bc.add_header(&side3.header).unwrap();
+        assert_eq!(chain.bc.best_header().unwrap(), side3.hash);
+
+        assert!(chain.bc.add_block(&side2).is_err());
+        assert_eq!(chain.bc.tip, tip);
+        assert_eq!(chain.utxo_set().utxo_map().unwrap(), utxo_map);
+        assert!(!chain.bc.is_invalid(&side1.hash).unwrap());
+        assert!(chain.bc.is_invalid(&side2.hash).unwrap());
+        assert!(chain.bc.is_invalid(&side3.hash).unwrap());
+        assert!(!chain.bc.is_invalid(&chain.bc.best_header().unwrap()).unwrap());
+        assert!(chain.bc.add_block(&side3).is_err());
+        assert_eq!(chain_verifier::verify_chain(&chain.bc).unwrap(), 3);
+    }
+
+    // 写入 tip 之前中断: 重新打开时按 UTXO 集所在的区块修复 tip, 或者把 UTXO 集推进到 tip
+    #[test]
+    fn reopening_repairs_an_interrupted_tip_write() {
+        let mut chain = TestChain::new("recover-tip");
+        let parent = chain.mine(Vec::new()).hash;
+        let block = chain.mine(Vec::new());
+
+        // UTXO 集已经接入 block, tip 仍然是 parent
+        chain.bc.db.insert("tip", parent.clone()).unwrap();
+        chain.reopen();
+        assert_eq!(chain.bc.tip, block.hash);
+        assert_eq!(chain_verifier::verify_chain(&chain.bc).unwrap(), 3);
+
+        // tip 已经是 block, UTXO 集仍然停在 parent
+        assert!(chain.utxo_set().revert(&block).unwrap());
+        assert_eq!(chain.bc.db.get(UTXO_TIP_KEY).unwrap().unwrap().as_ref(), parent.as_slice());
+        chain.reopen();
+        assert_eq!(chain.bc.tip, block.hash);
+        assert_eq!(chain.bc.db.get(UTXO_TIP_KEY).unwrap().unwrap().as_ref(), block.hash.as_slice());
+        assert_eq!(chain_verifier::verify_chain(&chain.bc).unwrap(), 3);
+    }
+}
@@ -1,15 +1,28 @@
-#[derive(Debug)]
-pub struct BlockHeader {  
-    // Version of the block (4 bytes)  
-    version: u32,  
-    // Hash of the previous block (32 bytes)  
-    prev_block_hash: Vec<u8>,  
-    // Merkle root (32 bytes)  
-    merkle_root: Vec<u8>,  
-    // Timestamp of the block (8 bytes)  
-    timestamp: u64,  
-    // Difficulty target (4 bytes)  
-    bits: u32,  
-    // Nonce (8 bytes)  
-    nonce: u64,  
-}
\ No newline at end of file
+use serde::{Serialize, Deserialize};
+use crate::error::Result;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BlockHeader {
+    // Version of the block (4 bytes)
+    pub version: u32,
+    // Hash of the previous block (32 bytes)
+    pub prev_block_hash: Vec<u8>,
+    // Merkle root (32 bytes)
+    pub merkle_root: Vec<u8>,
+    // Timestamp of the block (8 bytes)
+    pub timestamp: u64,
+    // Difficulty target (4 bytes)
+    pub bits: u32,
+    // Nonce (4 bytes)
+    pub nonce: u32,
+}
+
+impl BlockHeader {
+    //序列化
+    pub fn serialize(&self) -> Vec<u8> {
+        bincode::serialize(&self).unwrap()
+    }
+    pub fn deserialize_header(d: &[u8]) -> Result<BlockHeader> {
+        Ok(bincode::deserialize(d)?)
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::block::Block;
+use crate::block_chain::BlockChain;
+use crate::error::{Error, Result};
+use crate::proof_of_work::ProofOfWork;
+use crate::transactions::{Transaction, TxVerdict};
+use crate::UTXOset::{UTXOEntry, UTXOSet};
+use crate::functions;
+use crate::MAX_FUTURE_BLOCK_TIME;
+
+// 第一个未通过校验的区块及原因
+#[derive(Debug)]
+pub struct VerifyFailure {
+    pub height: usize,
+    pub hash: Vec<u8>,
+    pub reason: String,
+}
+
+impl VerifyFailure {
+    fn new(height: usize, block: &Block, reason: String) -> Self {
+        VerifyFailure {
+            height,
+            hash: block.hash.clone(),
+            reason,
+        }
+    }
+}
+
+// 从创世区块开始逐块校验整条链, 成功时返回校验过的区块数
+// 校验失败返回 Error::VerifyFailed, 读取数据库出错时返回对应的错误
+pub fn verify_chain(bc: &BlockChain) -> Result<usize> {
+    let blocks = bc.main_chain_blocks()?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs();
+
+    // 已确认的交易, 以及按 (txid, vout) 记录的未花费输出
+    let mut known_txs: HashMap<Vec<u8>, Transaction> = HashMap::new();
+    let mut unspent: HashMap<(Vec<u8>, usize), UTXOEntry> = HashMap::new();
+
+    for (height, block) in blocks.iter().enumerate() {
+        let fail = |reason: String| Error::VerifyFailed(VerifyFailure::new(height, block, reason));
+        let header = &block.header;
+
+        // 前一区块哈希链接
+        if height == 0 {
+            if !header.prev_block_hash.is_empty() {
+                return Err(fail("genesis block has a previous hash".to_string()));
+            }
+        } else if header.prev_block_hash != blocks[height - 1].hash {
+            return Err(fail("previous hash does not match the parent block".to_string()));
+        }
+
+        // 区块哈希与工作量证明
+        let pow = ProofOfWork::new(header);
+        if pow.hash() != block.hash {
+            return Err(fail("stored hash does not match the block header".to_string()));
+        }
+        if !pow.validate(bc.expected_bits(&header.prev_block_hash)?) {
+            return Err(fail(format!("invalid proof of work for bits {}", header.bits)));
+        }
+
+        // 时间戳
+        if height > 0 && header.timestamp < blocks[height - 1].header.timestamp {
+            return Err(fail("timestamp is earlier than the parent block".to_string()));
+        }
+        if header.timestamp > now + MAX_FUTURE_BLOCK_TIME {
+            return Err(fail("timestamp is too far in the future".to_string()));
+        }
+
+        // Merkle root
+        if block.transactions.is_empty() {
+            return Err(fail("block has no transactions".to_string()));
+        }
+        if header.merkle_root != Block::hash_transactions(&block.transactions) {
+            return Err(fail("merkle root does not match the transactions".to_string()));
+        }
+
+        // Coinbase 规则: 每个区块恰好一笔
+        let coinbases: Vec<&Transaction> = block.transactions.iter().filter(|tx| tx.is_coinbase()).collect();
+        if coinbases.len() != 1 {
+            return Err(fail(format!("expected 1 coinbase transaction, found {}", coinbases.len())));
+        }
+
+        // 交易签名与双花
+        let mut fees: i64 = 0;
+        for tx in &block.transactions {
+            if !tx.is_coinbase() {
+                let mut prev_txs = HashMap::new();
+                for vin in &tx.inputs {
+                    match known_txs.get(&vin.transcation_id) {
+                        Some(prev_tx) => {
+                            prev_txs.insert(prev_tx.id.clone(), prev_tx.clone());
+                        }
+                        None => {
+                            return Err(fail(format!(
+                                "transaction {} spends unknown transaction {}",
+                                hex::encode(&tx.id),
+                                hex::encode(&vin.transcation_id)
+                            )));
+                        }
+                    }
+                }
+                for (in_id, vin) in tx.inputs.iter().enumerate() {
+                    let entry = match unspent.remove(&(vin.transcation_id.clone(), vin.vout)) {
+                        Some(entry) => entry,
+                        None => {
+                            return Err(fail(format!(
+                                "transaction {} spends missing or already spent output {}:{}",
+                                hex::encode(&tx.id),
+                                hex::encode(&vin.transcation_id),
+                                vin.vout
+                            )));
+                        }
+                    };
+                    if !entry.is_mature(height as u64, bc.config.coinbase_maturity) {
+                        let verdict = TxVerdict::ImmatureCoinbase { input: in_id };
+                        return Err(fail(format!("transaction {}: {}", hex::encode(&tx.id), verdict)));
+                    }
+                }
+                let verdict = tx.verify(&prev_txs);
+                if !verdict.is_valid() {
+                    return Err(fail(format!("transaction {}: {}", hex::encode(&tx.id), verdict)));
+                }
+                fees += tx.fee(&prev_txs);
+            } else {
+                let verdict = tx.verify(&HashMap::new());
+                if !verdict.is_valid() {
+                    return Err(fail(format!("transaction {}: {}", hex::encode(&tx.id), verdict)));
+                }
+            }
+            for (out_idx, out) in tx.outputs.iter().enumerate() {
+                let entry = UTXOEntry {
+                    output: out.clone(),
+                    height: height as u64,
+                    is_coinbase: tx.is_coinbase(),
+                };
+                if unspent.insert((tx.id.clone(), out_idx), entry).is_some() {
+                    return Err(fail(format!(
+                        "transaction {} overwrites unspent output {}",
+                        hex::encode(&tx.id),
+                        out_idx
+                    )));
+                }
+            }
+            known_txs.insert(tx.id.clone(), tx.clone());
+        }
+
+        // coinbase 最多领取该高度的补贴加上本区块交易的手续费
+        let subsidy = functions::block_subsidy(&bc.config, height as u64);
+        if let Some(out_idx) = coinbases[0].outputs.iter().position(|out| out.value < 0) {
+            return Err(fail(format!("coinbase output {} is negative", out_idx)));
+        }
+        let reward: i64 = coinbases[0].outputs.iter().map(|out| out.value as i64).sum();
+        if reward > subsidy as i64 + fees {
+            return Err(fail(format!(
+                "coinbase pays {} which exceeds subsidy {} plus fees {}",
+                reward, subsidy, fees
+            )));
+        }
+    }
+
+    // 持久化的 utxoBucket 必须与重新构建的 UTXO 集一致
+    let utxo_set = UTXOSet {
+        blockchain: bc.clone(),
+    };
+    if utxo_set.utxo_map()? != bc.find_utxo()? {
+        if let Some(tip) = blocks.last() {
+            return Err(Error::VerifyFailed(VerifyFailure::new(
+                blocks.len() - 1,
+                tip,
+                "utxoBucket does not match the UTXO set rebuilt from the chain".to_string(),
+            )));
+        }
+    }
+    if !utxo_set.check_address_index()? {
+        if let Some(tip) = blocks.last() {
+            return Err(Error::VerifyFailed(VerifyFailure::new(
+                blocks.len() - 1,
+                tip,
+                "addressIndex does not match utxoBucket".to_string(),
+            )));
+        }
+    }
+
+    Ok(blocks.len())
+}
@@ -0,0 +1,271 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand::seq::SliceRandom;
+
+// 分支定界最多尝试的搜索步数, 超过后放弃精确匹配
+const BNB_MAX_TRIES: usize = 100_000;
+
+// 可以作为新交易输入的一个已成熟的未花费输出
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub tx_id: Vec<u8>,
+    pub vout: usize,
+    pub value: i32,
+}
+
+// 交易手续费: 固定部分加上每个输入的费用, 输入越多交易越大
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Fee {
+    pub base: i32,
+    pub per_input: i32,
+}
+
+impl Fee {
+    pub fn total(&self, inputs: usize) -> i64 {
+        self.base as i64 + self.per_input as i64 * inputs as i64
+    }
+}
+
+// 从候选输出中选出一组, 总额覆盖 target 以及每个选中输入的 input_fee, 选不出时返回 None
+pub trait CoinSelector {
+    fn select(&self, candidates: &[Candidate], target: i64, input_fee: i32) -> Option<Vec<Candidate>>;
+}
+
+// 输出的有效金额: 面值减去把它作为输入的费用, 不为正的输出花费它得不偿失
+fn effective_value(candidate: &Candidate, input_fee: i32) -> i64 {
+    candidate.value as i64 - input_fee as i64
+}
+
+// 按给定顺序累加有效金额, 直到覆盖 target
+fn accumulate<'a>(
+    ordered: impl Iterator<Item = &'a Candidate>,
+    target: i64,
+    input_fee: i32,
+) -> Option<Vec<Candidate>> {
+    let mut selected = Vec::new();
+    let mut accumulated: i64 = 0;
+    for candidate in ordered {
+        let value = effective_value(candidate, input_fee);
+        if value <= 0 {
+            continue;
+        }
+        accumulated += value;
+        selected.push(candidate.clone());
+        if accumulated >= target {
+            return Some(selected);
+        }
+    }
+    None
+}
+
+// 先用面值最大的输出, 输入最少, 手续费最低
+pub struct LargestFirst;
+
+impl CoinSelector for LargestFirst {
+    fn select(&self, candidates: &[Candidate], target: i64, input_fee: i32) -> Option<Vec<Candidate>> {
+        let mut ordered: Vec<&Candidate> = candidates.iter().collect();
+        ordered.sort_by_key(|candidate| std::cmp::Reverse(candidate.value));
+        accumulate(ordered.into_iter(), target, input_fee)
+    }
+}
+
+// 先用面值最小的输出, 顺便合并零散的小额输出
+pub struct SmallestFirst;
+
+impl CoinSelector for SmallestFirst {
+    fn select(&self, candidates: &[Candidate], target: i64, input_fee: i32) -> Option<Vec<Candidate>> {
+        let mut ordered: Vec<&Candidate> = candidates.iter().collect();
+        ordered.sort_by_key(|candidate| candidate.value);
+        accumulate(ordered.into_iter(), target, input_fee)
+    }
+}
+
+// 随机顺序, 避免从输入的选择方式推断出钱包的行为
+pub struct Random;
+
+impl CoinSelector for Random {
+    fn select(&self, candidates: &[Candidate], target: i64, input_fee: i32) -> Option<Vec<Candidate>> {
+        let mut ordered: Vec<&Candidate> = candidates.iter().collect();
+        ordered.shuffle(&mut rand::thread_rng());
+        accumulate(ordered.into_iter(), target, input_fee)
+    }
+}
+
+// 分支定界: 寻找有效金额之和落在 [target, target + input_fee] 内的组合, 这样不需要找零输出,
+// 多出的部分不超过以后花费找零的费用, 直接留给矿工; 找不到时退回到 LargestFirst
+pub struct BranchAndBound;
+
+impl CoinSelector for BranchAndBound {
+    fn select(&self, candidates: &[Candidate], target: i64, input_fee: i32) -> Option<Vec<Candidate>> {
+        let mut pool: Vec<(&Candidate, i64)> = candidates
+            .iter()
+            .map(|candidate| (candidate, effective_value(candidate, input_fee)))
+            .filter(|(_, value)| *value > 0)
+            .collect();
+        pool.sort_by_key(|(_, value)| std::cmp::Reverse(*value));
+
+        let values: Vec<i64> = pool.iter().map(|(_, value)| *value).collect();
+        // remaining[i]: 从第 i 个开始全部选上还能增加的金额
+        let mut remaining = vec![0i64; values.len() + 1];
+        for i in (0..values.len()).rev() {
+            remaining[i] = remaining[i + 1] + values[i];
+        }
+
+        let mut search = BnbSearch {
+            values: &values,
+            remaining: &remaining,
+            target,
+            upper: target + input_fee.max(0) as i64,
+            tries: 0,
+            selected: Vec::new(),
+        };
+        match search.run(0, 0) {
+            Some(indexes) => Some(indexes.into_iter().map(|i| pool[i].0.clone()).collect()),
+            None => LargestFirst.select(candidates, target, input_fee),
+        }
+    }
+}
+
+struct BnbSearch<'a> {
+    values: &'a [i64],
+    remaining: &'a [i64],
+    target: i64,
+    upper: i64,
+    tries: usize,
+    selected: Vec<usize>,
+}
+
+impl BnbSearch<'_> {
+    // 深度优先, 每个输出先尝试选上再尝试跳过
+    fn run(&mut self, index: usize, sum: i64) -> Option<Vec<usize>> {
+        self.tries += 1;
+        if self.tries > BNB_MAX_TRIES || sum > self.upper || sum + self.remaining[index] < self.target {
+            return None;
+        }
+        if sum >= self.target {
+            return Some(self.selected.clone());
+        }
+        if index == self.values.len() {
+            return None;
+        }
+
+        self.selected.push(index);
+        if let Some(found) = self.run(index + 1, sum + self.values[index]) {
+            return Some(found);
+        }
+        self.selected.pop();
+        self.run(index + 1, sum)
+    }
+}
+
+// send 命令可以选择的策略
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    LargestFirst,
+    SmallestFirst,
+    BranchAndBound,
+    Random,
+}
+
+impl Strategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::LargestFirst => "largest-first",
+            Strategy::SmallestFirst => "smallest-first",
+            Strategy::BranchAndBound => "bnb",
+            Strategy::Random => "random",
+        }
+    }
+
+    pub fn selector(&self) -> Box<dyn CoinSelector> {
+        match self {
+            Strategy::LargestFirst => Box::new(LargestFirst),
+            Strategy::SmallestFirst => Box::new(SmallestFirst),
+            Strategy::BranchAndBound => Box::new(BranchAndBound),
+            Strategy::Random => Box::new(Random),
+        }
+    }
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "largest-first" => Ok(Strategy::LargestFirst),
+            "smallest-first" => Ok(Strategy::SmallestFirst),
+            "bnb" => Ok(Strategy::BranchAndBound),
+            "random" => Ok(Strategy::Random),
+            _ => Err(format!(
+                "unknown coin selection strategy '{}', expected largest-first, smallest-first, bnb or random",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidates(values: &[i32]) -> Vec<Candidate> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(vout, value)| Candidate { tx_id: vec![1], vout, value: *value })
+            .collect()
+    }
+
+    fn total(selected: &[Candidate], input_fee: i32) -> i64 {
+        selected.iter().map(|candidate| effective_value(candidate, input_fee)).sum()
+    }
+
+    // 只有 3 + 7 恰好等于 10, LargestFirst 会选 8 + 7 并产生找零
+    #[test]
+    fn branch_and_bound_finds_changeless_match() {
+        let pool = candidates(&[8, 7, 5, 3]);
+        let selected = BranchAndBound.select(&pool, 10, 0).unwrap();
+        assert_eq!(total(&selected, 0), 10);
+        let mut values: Vec<i32> = selected.iter().map(|candidate| candidate.value).collect();
+        values.sort();
+        assert_eq!(values, vec![3, 7]);
+    }
+
+    // 有效金额之和落在 [target, target + input_fee] 内即可, 并扣除每个输入的费用
+    #[test]
+    fn branch_and_bound_accepts_excess_up_to_input_fee() {
+        let pool = candidates(&[14, 9, 6]);
+        let selected = BranchAndBound.select(&pool, 10, 2).unwrap();
+        let sum = total(&selected, 2);
+        assert!((10..=12).contains(&sum), "selected {:?}", selected);
+    }
+
+    #[test]
+    fn branch_and_bound_skips_outputs_not_worth_spending() {
+        let pool = candidates(&[1, 2, 12]);
+        let selected = BranchAndBound.select(&pool, 10, 2).unwrap();
+        assert_eq!(selected, vec![pool[2].clone()]);
+    }
+
+    #[test]
+    fn branch_and_bound_falls_back_to_largest_first() {
+        let pool = candidates(&[6, 6, 6]);
+        let selected = BranchAndBound.select(&pool, 10, 0).unwrap();
+        assert_eq!(selected, LargestFirst.select(&pool, 10, 0).unwrap());
+        assert_eq!(total(&selected, 0), 12);
+    }
+
+    #[test]
+    fn branch_and_bound_returns_none_without_enough_funds() {
+        let pool = candidates(&[4, 3, 2]);
+        assert_eq!(BranchAndBound.select(&pool, 10, 0), None);
+        assert_eq!(BranchAndBound.select(&[], 1, 0), None);
+    }
+}
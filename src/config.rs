@@ -0,0 +1,127 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::{COINBASE_MATURITY, COOKIE_FILE, DB_FILE, GENESIS, HALVING_INTERVAL, SUBSIDY, TARGET_BITS, WALLET_FILE};
+
+// 不同网络的区块链数据, 地址和钱包互不相通
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+    Regtest,
+}
+
+impl Network {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            _ => Err(format!("unknown network '{}', expected mainnet, testnet or regtest", s)),
+        }
+    }
+}
+
+// 一条链的全部参数: 数据目录, 网络, 地址版本号, 创世区块, 初始难度和区块补贴
+#[derive(Debug, Clone)]
+pub struct ChainConfig {
+    pub datadir: PathBuf,
+    pub network: Network,
+    pub address_version: u8,
+    // 节点之间每条消息开头的网络标识, 不同网络的节点无法互相连接
+    pub magic: [u8; 4],
+    // 节点 JSON-RPC 服务默认监听的本地端口
+    pub rpc_port: u16,
+    pub genesis_reward: i32,
+    pub genesis_data: String,
+    // 创世区块的难度, 同时也是难度调整的下限
+    pub target_bits: u32,
+    // 创世之后的初始区块补贴, 每 halving_interval 个区块减半
+    pub subsidy: i32,
+    pub halving_interval: u64,
+    // coinbase 输出需要在产生它的区块之后再经过的区块数才能花费
+    pub coinbase_maturity: u64,
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self::new(Network::Mainnet, ".")
+    }
+}
+
+impl ChainConfig {
+    pub fn new(network: Network, datadir: impl AsRef<Path>) -> Self {
+        let mainnet = ChainConfig {
+            datadir: datadir.as_ref().to_path_buf(),
+            network,
+            address_version: 0x00,
+            magic: [0xf9, 0xbe, 0xb4, 0xd9],
+            rpc_port: 8332,
+            genesis_reward: GENESIS,
+            genesis_data: "Genesis Block".to_string(),
+            target_bits: TARGET_BITS,
+            subsidy: SUBSIDY,
+            halving_interval: HALVING_INTERVAL,
+            coinbase_maturity: COINBASE_MATURITY,
+        };
+        match network {
+            Network::Mainnet => mainnet,
+            Network::Testnet => ChainConfig {
+                address_version: 0x6f,
+                magic: [0x0b, 0x11, 0x09, 0x07],
+                rpc_port: 18332,
+                genesis_data: "Testnet Genesis Block".to_string(),
+                ..mainnet
+            },
+            // 本地测试网络: 难度低, 出块快
+            Network::Regtest => ChainConfig {
+                address_version: 0x6f,
+                magic: [0xfa, 0xbf, 0xb5, 0xda],
+                rpc_port: 18443,
+                genesis_data: "Regtest Genesis Block".to_string(),
+                target_bits: 8,
+                coinbase_maturity: 2,
+                ..mainnet
+            },
+        }
+    }
+
+    // 主网直接使用 datadir, 其他网络使用以网络名命名的子目录
+    pub fn chain_dir(&self) -> PathBuf {
+        match self.network {
+            Network::Mainnet => self.datadir.clone(),
+            network => self.datadir.join(network.name()),
+        }
+    }
+
+    pub fn db_path(&self) -> PathBuf {
+        self.chain_dir().join(DB_FILE)
+    }
+
+    pub fn wallet_path(&self) -> PathBuf {
+        self.chain_dir().join(WALLET_FILE)
+    }
+
+    pub fn cookie_path(&self) -> PathBuf {
+        self.chain_dir().join(COOKIE_FILE)
+    }
+}
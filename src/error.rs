@@ -0,0 +1,136 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use sled::transaction::TransactionError;
+
+use crate::chain_verifier::VerifyFailure;
+use crate::transactions::TxVerdict;
+
+// 库中所有可能失败的操作统一返回这个错误类型, 不再 panic 或 process::exit
+#[derive(Debug)]
+pub enum Error {
+    InsufficientFunds { required: i64, available: i64 },
+    InvalidAddress(String),
+    WalletNotFound(String),
+    TxNotFound(Vec<u8>),
+    BlockNotFound(Vec<u8>),
+    NoBlockchain,
+    BlockchainExists(PathBuf),
+    IncompatibleDatabase(PathBuf),
+    SupplyExceeded { issued: i64, scheduled: i64 },
+    InvalidSignature { input: usize },
+    InvalidTransaction(TxVerdict),
+    MempoolRejected(String),
+    Protocol(String),
+    InvalidBlock(String),
+    MiningCancelled,
+    VerifyFailed(VerifyFailure),
+    SigningFailed,
+    KeyGeneration,
+    InvalidKey,
+    WalletLocked,
+    WrongPassphrase,
+    WalletEncryption(String),
+    InvalidMnemonic(String),
+    HdWallet(String),
+    Io(std::io::Error),
+    DbError(sled::Error),
+    Serialization(bincode::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // 读写数据库或文件出错, 而不是数据本身无效; 调用者应当中止而不是拒绝数据
+    pub fn is_storage(&self) -> bool {
+        matches!(self, Error::DbError(_) | Error::Io(_) | Error::Serialization(_))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InsufficientFunds { required, available } => {
+                write!(f, "not enough funds: required {}, available {}", required, available)
+            }
+            Error::InvalidAddress(address) => write!(f, "invalid address: {}", address),
+            Error::WalletNotFound(address) => write!(f, "no wallet for address {}", address),
+            Error::TxNotFound(id) => write!(f, "transaction {} not found", hex::encode(id)),
+            Error::BlockNotFound(hash) => write!(f, "block {} not found", hex::encode(hash)),
+            Error::NoBlockchain => write!(f, "no blockchain, create one first"),
+            Error::BlockchainExists(path) => write!(f, "a blockchain already exists in {}", path.display()),
+            Error::IncompatibleDatabase(path) => write!(
+                f,
+                "incompatible database in {}: it was written by another version, move it away or use another --datadir",
+                path.display()
+            ),
+            Error::SupplyExceeded { issued, scheduled } => {
+                write!(f, "issued supply {} exceeds the schedule by {}", issued, issued - scheduled)
+            }
+            Error::InvalidSignature { input } => write!(f, "input {} has an invalid signature", input),
+            Error::InvalidTransaction(verdict) => write!(f, "invalid transaction: {}", verdict),
+            Error::MempoolRejected(reason) => write!(f, "transaction rejected by the mempool: {}", reason),
+            Error::Protocol(reason) => write!(f, "protocol error: {}", reason),
+            Error::InvalidBlock(reason) => write!(f, "invalid block: {}", reason),
+            Error::MiningCancelled => write!(f, "mining was cancelled"),
+            Error::VerifyFailed(failure) => write!(
+                f,
+                "block {} ({}) failed verification: {}",
+                failure.height,
+                hex::encode(&failure.hash),
+                failure.reason
+            ),
+            Error::SigningFailed => write!(f, "failed to sign transaction"),
+            Error::KeyGeneration => write!(f, "failed to generate key pair"),
+            Error::InvalidKey => write!(f, "invalid PKCS#8 key document"),
+            Error::WalletLocked => write!(f, "wallet is locked, unlock it with the passphrase first"),
+            Error::WrongPassphrase => write!(f, "wrong wallet passphrase"),
+            Error::WalletEncryption(reason) => write!(f, "wallet encryption error: {}", reason),
+            Error::InvalidMnemonic(reason) => write!(f, "invalid mnemonic: {}", reason),
+            Error::HdWallet(reason) => write!(f, "HD wallet error: {}", reason),
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::DbError(e) => write!(f, "database error: {}", e),
+            Error::Serialization(e) => write!(f, "serialization error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<sled::Error> for Error {
+    fn from(e: sled::Error) -> Self {
+        Error::DbError(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+// 事务中主动中止时带出的是库自己的错误, 其余是数据库错误
+impl From<TransactionError<Error>> for Error {
+    fn from(e: TransactionError<Error>) -> Self {
+        match e {
+            TransactionError::Abort(e) => e,
+            TransactionError::Storage(e) => Error::DbError(e),
+        }
+    }
+}
+
+impl From<bincode::Error> for Error {
+    fn from(e: bincode::Error) -> Self {
+        Error::Serialization(e)
+    }
+}
+
+// 签名错误单独成为 InvalidSignature, 其余的校验失败保留完整的 TxVerdict
+impl From<TxVerdict> for Error {
+    fn from(verdict: TxVerdict) -> Self {
+        match verdict {
+            TxVerdict::InvalidSignature { input } => Error::InvalidSignature { input },
+            verdict => Error::InvalidTransaction(verdict),
+        }
+    }
+}
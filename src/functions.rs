@@ -1,8 +1,9 @@
 use ripemd::Ripemd160;  
 use sha3::{Sha3_256, Digest}; 
-use rust_base58::{base58, ToBase58, FromBase58};
+use rust_base58::{FromBase58, ToBase58};
 use crate::ADDRESS_CHECKSUM_LEN;  
-use crate::functions;
+use crate::config::ChainConfig;
+use crate::error::{Error, Result};
 
 
 
@@ -26,14 +27,26 @@ pub fn checksum(payload: &Vec<u8>) -> Vec<u8> {
     let first_sha = hasher.finalize();  
     
     let mut second_hasher = Sha3_256::new();  
-    second_hasher.update(&first_sha);  
+    second_hasher.update(first_sha);  
     let second_sha = second_hasher.finalize();  
     
     second_sha[..ADDRESS_CHECKSUM_LEN].to_vec()
 }
 
-pub fn validate_address(address: &str) -> bool {  
-    let pub_key_hash = address.from_base58().expect("Invalid Base58 string");  
+// 地址的版本字节必须与 config 所在的网络一致  
+pub fn validate_address(address: &str, config: &ChainConfig) -> bool {  
+    match address.from_base58() {  
+        Ok(payload) => check_payload(&payload) && payload[0] == config.address_version,  
+        Err(_) => false,  
+    }  
+}  
+
+// 只检查地址的长度和校验和, 不限定网络  
+fn check_payload(pub_key_hash: &[u8]) -> bool {  
+    // 至少需要版本字节和校验和  
+    if pub_key_hash.len() <= 1 + ADDRESS_CHECKSUM_LEN {  
+        return false;  
+    }  
 
     // 提取实际校验和  
     let actual_checksum = pub_key_hash[pub_key_hash.len() - ADDRESS_CHECKSUM_LEN..].to_vec();  
@@ -51,11 +64,49 @@ pub fn validate_address(address: &str) -> bool {
     actual_checksum == target_checksum  
 }  
 
-pub  fn address_to_pubkeyhash(address: &str) -> Vec<u8> {
-    let full_payload = address.from_base58().expect("Invalid Base58 string"); 
-    let pub_key_hash = full_payload[1..full_payload.len() - 4].to_vec();  
+// 公钥哈希加上 config 所在网络的版本字节和校验和, 编码为 Base58 地址  
+pub fn pubkeyhash_to_address(pub_key_hash: &[u8], config: &ChainConfig) -> String {  
+    let mut payload = vec![config.address_version];  
+    payload.extend_from_slice(pub_key_hash);  
+    let checksum = checksum(&payload);  
+    payload.extend(checksum);  
+    payload.to_base58()  
+}  
 
-    pub_key_hash  
+// 地址所属的网络由调用方用 validate_address 检查
+pub  fn address_to_pubkeyhash(address: &str) -> Result<Vec<u8>> {
+    let full_payload = address.from_base58().map_err(|_| Error::InvalidAddress(address.to_string()))?; 
+    if !check_payload(&full_payload) {
+        return Err(Error::InvalidAddress(address.to_string()));
+    }
+    Ok(full_payload[1..full_payload.len() - ADDRESS_CHECKSUM_LEN].to_vec())
 
-}  
+}
 
+// 截至 height (含) 按计划应发行的总量: 创世区块 genesis_reward, 之后从 subsidy 开始
+// 每 halving_interval 个区块减半, 补贴减到 0 之后总量不再增加
+pub fn scheduled_supply(config: &ChainConfig, height: u64) -> i64 {
+    let mut supply = config.genesis_reward as i64;
+    let mut remaining = height;
+    let mut reward = config.subsidy as i64;
+    while remaining > 0 && reward > 0 {
+        let blocks = remaining.min(config.halving_interval);
+        supply += blocks as i64 * reward;
+        remaining -= blocks;
+        reward /= 2;
+    }
+    supply
+}
+
+// 总发行量上限
+pub fn max_supply(config: &ChainConfig) -> i64 {
+    scheduled_supply(config, u64::MAX)
+}
+
+// 高度为 height 的区块可以新发行的补贴
+pub fn block_subsidy(config: &ChainConfig, height: u64) -> i32 {
+    if height == 0 {
+        return scheduled_supply(config, 0) as i32;
+    }
+    (scheduled_supply(config, height) - scheduled_supply(config, height - 1)) as i32
+}
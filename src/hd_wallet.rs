@@ -0,0 +1,221 @@
+use bip39::Mnemonic;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::elliptic_curve::{Field, PrimeField};
+use p256::{NonZeroScalar, Scalar, SecretKey};
+use ring::hmac;
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::error::{Error, Result};
+
+// SLIP-0010 中 NIST P-256 曲线主密钥的 HMAC key
+const MASTER_KEY: &[u8] = b"Nist256p1 seed";
+// 硬化派生的序号从 2^31 开始
+const HARDENED: u32 = 0x8000_0000;
+// 派生路径 m/44'/1'/0'/0'/i', 全部使用硬化派生
+pub const HD_PATH: [u32; 4] = [44, 1, 0, 0];
+// 助记词对应的熵长度, 16 字节为 12 个单词
+const ENTROPY_LEN: usize = 16;
+
+// ring 生成的 P-256 PKCS#8 文档的固定部分, 私钥和公钥分别填在两段之后
+const PKCS8_PREFIX: [u8; 36] = [
+    0x30, 0x81, 0x87, 0x02, 0x01, 0x00, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02,
+    0x01, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x04, 0x6d, 0x30, 0x6b, 0x02,
+    0x01, 0x01, 0x04, 0x20,
+];
+const PKCS8_PUBLIC_KEY_PREFIX: [u8; 5] = [0xa1, 0x44, 0x03, 0x42, 0x00];
+
+// 扩展私钥: 私钥标量和链码
+struct ExtendedKey {
+    key: Scalar,
+    chain_code: [u8; 32],
+}
+
+// 生成新的 12 词助记词
+pub fn generate_mnemonic() -> Result<String> {
+    let mut entropy = [0u8; ENTROPY_LEN];
+    SystemRandom::new().fill(&mut entropy).map_err(|_| Error::KeyGeneration)?;
+    let mnemonic = Mnemonic::from_entropy(&entropy).map_err(|e| Error::InvalidMnemonic(e.to_string()))?;
+    Ok(mnemonic.to_string())
+}
+
+// 校验助记词 (单词表和校验和), 返回以单个空格分隔的小写形式
+pub fn normalize_mnemonic(phrase: &str) -> Result<String> {
+    let words: Vec<String> = phrase.split_whitespace().map(|word| word.to_lowercase()).collect();
+    let mnemonic = Mnemonic::parse_normalized(&words.join(" "))
+        .map_err(|e| Error::InvalidMnemonic(e.to_string()))?;
+    Ok(mnemonic.to_string())
+}
+
+// 按 m/44'/1'/0'/0'/index' 派生第 index 个密钥, 返回可以交给 Wallet::from_pkcs8 的 PKCS#8 文档
+pub fn derive_pkcs8(phrase: &str, index: u32) -> Result<Vec<u8>> {
+    let mnemonic = Mnemonic::parse_normalized(phrase).map_err(|e| Error::InvalidMnemonic(e.to_string()))?;
+    let seed = mnemonic.to_seed_normalized("");
+
+    let mut extended = master_key(&seed);
+    for child in HD_PATH.iter().chain(std::iter::once(&index)) {
+        extended = derive_child(&extended, *child)?;
+    }
+    pkcs8_from_scalar(&extended.key)
+}
+
+fn master_key(seed: &[u8]) -> ExtendedKey {
+    let key = hmac::Key::new(hmac::HMAC_SHA512, MASTER_KEY);
+    let mut data = seed.to_vec();
+    loop {
+        let i = hmac::sign(&key, &data);
+        let (il, ir) = i.as_ref().split_at(32);
+        if let Some(scalar) = non_zero_scalar(il) {
+            return ExtendedKey {
+                key: scalar,
+                chain_code: ir.try_into().expect("HMAC-SHA512 output is 64 bytes"),
+            };
+        }
+        // IL 为 0 或不小于曲线阶时, 用整个 I 作为新的输入重新计算
+        data = i.as_ref().to_vec();
+    }
+}
+
+fn derive_child(parent: &ExtendedKey, index: u32) -> Result<ExtendedKey> {
+    if index >= HARDENED {
+        return Err(Error::HdWallet(format!("derivation index {} is out of range", index)));
+    }
+    let child_index = (index | HARDENED).to_be_bytes();
+    let key = hmac::Key::new(hmac::HMAC_SHA512, &parent.chain_code);
+
+    let mut data = vec![0u8];
+    data.extend_from_slice(&parent.key.to_repr());
+    data.extend_from_slice(&child_index);
+    loop {
+        let i = hmac::sign(&key, &data);
+        let (il, ir) = i.as_ref().split_at(32);
+        if let Some(tweak) = scalar(il) {
+            let child = tweak + parent.key;
+            if !bool::from(child.is_zero()) {
+                return Ok(ExtendedKey {
+                    key: child,
+                    chain_code: ir.try_into().expect("HMAC-SHA512 output is 64 bytes"),
+                });
+            }
+        }
+        // 结果无效时按 SLIP-0010 用 0x01 || IR || index 重试
+        data = vec![1u8];
+        data.extend_from_slice(ir);
+        data.extend_from_slice(&child_index);
+    }
+}
+
+// 小于曲线阶的 32 字节大端整数
+fn scalar(bytes: &[u8]) -> Option<Scalar> {
+    let bytes: [u8; 32] = bytes.try_into().ok()?;
+    Option::from(Scalar::from_repr(bytes.into()))
+}
+
+fn non_zero_scalar(bytes: &[u8]) -> Option<Scalar> {
+    scalar(bytes).filter(|s| !bool::from(s.is_zero()))
+}
+
+fn pkcs8_from_scalar(key: &Scalar) -> Result<Vec<u8>> {
+    let non_zero: NonZeroScalar = Option::from(NonZeroScalar::new(*key)).ok_or(Error::KeyGeneration)?;
+    let secret = SecretKey::from(non_zero);
+    let public_key = secret.public_key().to_encoded_point(false);
+
+    let mut pkcs8 = PKCS8_PREFIX.to_vec();
+    pkcs8.extend_from_slice(&secret.to_bytes());
+    pkcs8.extend_from_slice(&PKCS8_PUBLIC_KEY_PREFIX);
+    pkcs8.extend_from_slice(public_key.as_bytes());
+    Ok(pkcs8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::Wallet;
+    use ring::signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1};
+
+    const MNEMONIC: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    fn key_hex(key: &ExtendedKey) -> (String, String) {
+        (hex::encode(key.chain_code), hex::encode(key.key.to_repr()))
+    }
+
+    fn derive_path(seed: &[u8], path: &[u32]) -> ExtendedKey {
+        let mut key = master_key(seed);
+        for index in path {
+            key = derive_child(&key, *index).unwrap();
+        }
+        key
+    }
+
+    // SLIP-0010 nist256p1 测试向量中只含硬化派生的部分 (序号不带 H 标记, derive_child 总是硬化派生)
+    #[test]
+    fn slip10_test_vector_1() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        assert_eq!(
+            key_hex(&derive_path(&seed, &[])),
+            (
+                "beeb672fe4621673f722f38529c07392fecaa61015c80c34f29ce8b41b3cb6ea".to_string(),
+                "612091aaa12e22dd2abef664f8a01a82cae99ad7441b7ef8110424915c268bc2".to_string(),
+            )
+        );
+        assert_eq!(
+            key_hex(&derive_path(&seed, &[0])),
+            (
+                "3460cea53e6a6bb5fb391eeef3237ffd8724bf0a40e94943c98b83825342ee11".to_string(),
+                "6939694369114c67917a182c59ddb8cafc3004e63ca5d3b84403ba8613debc0c".to_string(),
+            )
+        );
+    }
+
+    #[test]
+    fn slip10_derivation_retry() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        assert_eq!(
+            key_hex(&derive_path(&seed, &[28578])),
+            (
+                "e94c8ebe30c2250a14713212f6449b20f3329105ea15b652ca5bdfc68f6c65c2".to_string(),
+                "06f0db126f023755d0b8d86d4591718a5210dd8d024e3e14b6159d63f53aa669".to_string(),
+            )
+        );
+    }
+
+    #[test]
+    fn slip10_seed_retry() {
+        let seed = hex::decode("a7305bc8df8d0951f0cb224c0e95d7707cbdf2c6ce7e8d481fec69c7ff5e9446").unwrap();
+        assert_eq!(
+            key_hex(&derive_path(&seed, &[])),
+            (
+                "7762f9729fed06121fd13f326884c82f59aa95c57ac492ce8c9654e60efd130c".to_string(),
+                "3b8c18469a4634517d6d0b65448f8e6c62091b45540a1743c5846be55d47d88f".to_string(),
+            )
+        );
+    }
+
+    #[test]
+    fn derive_child_rejects_hardened_index() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        assert!(derive_child(&master_key(&seed), HARDENED).is_err());
+    }
+
+    // derive_pkcs8 生成的文档能被 ring 解析, 公钥与派生出的私钥一致, 签名可以验证
+    #[test]
+    fn derive_pkcs8_round_trip() {
+        let pkcs8 = derive_pkcs8(MNEMONIC, 0).unwrap();
+        let wallet = Wallet::from_pkcs8(&pkcs8).unwrap();
+
+        let seed = Mnemonic::parse_normalized(MNEMONIC).unwrap().to_seed_normalized("");
+        let path: Vec<u32> = HD_PATH.iter().copied().chain(std::iter::once(0)).collect();
+        let key = derive_path(&seed, &path).key;
+        let secret = SecretKey::from(Option::<NonZeroScalar>::from(NonZeroScalar::new(key)).unwrap());
+        assert_eq!(wallet.public_key, secret.public_key().to_encoded_point(false).as_bytes());
+
+        let message = b"derive_pkcs8 round trip";
+        let signature = wallet.key_pair().unwrap().sign(&SystemRandom::new(), message).unwrap();
+        UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, &wallet.public_key)
+            .verify(message, signature.as_ref())
+            .unwrap();
+
+        assert_eq!(derive_pkcs8(MNEMONIC, 0).unwrap(), pkcs8);
+        assert_ne!(derive_pkcs8(MNEMONIC, 1).unwrap(), pkcs8);
+    }
+}
@@ -1,27 +1,44 @@
+#![allow(non_snake_case)]
+
 pub mod block;
 pub mod block_header;
 pub mod block_chain;
 pub mod proof_of_work;
+pub mod miner;
 pub mod bc_iter;
 pub mod Interface;
 pub mod transactions;
 pub mod wallet;
+pub mod hd_wallet;
 pub mod functions;
 pub mod UTXOset;
 pub mod merkle_tree;
-
-pub const TARGET_BITS: u32 = 12; 
+pub mod chain_verifier;
+pub mod error;
+pub mod config;
+pub mod coin_selection;
+pub mod mempool;
+pub mod protocol;
+pub mod node;
+pub mod rpc;
+
+pub const BLOCK_VERSION: u32 = 1;
+pub const TARGET_BITS: u32 = 12; // 主网创世区块的难度
+pub const MAX_TARGET_BITS: u32 = 64;
+pub const RETARGET_INTERVAL: usize = 10; // 每 10 个区块调整一次难度
+pub const TARGET_BLOCK_TIME: u64 = 10; // 期望出块时间 (秒)
 pub const MAX_NONCE: u32 = 1_000_000_000; 
-pub const GENESIS: i32 = 77;
-pub const SUBSIDY: i32 = 70;
+// 主网的补贴计划, 总发行量上限为 GENESIS + (70 + 35 + 17 + 8 + 4 + 2 + 1) * HALVING_INTERVAL = 13_777
+pub const GENESIS: i32 = 77; // 创世区块奖励
+pub const SUBSIDY: i32 = 70; // 创世之后的初始区块奖励
+pub const HALVING_INTERVAL: u64 = 100; // 每 100 个区块奖励减半
+pub const COINBASE_MATURITY: u64 = 3; // 主网和测试网的 coinbase 输出需要再经过 3 个区块才能花费
+pub const MAX_FUTURE_BLOCK_TIME: u64 = 2 * 60 * 60; // 区块时间戳最多领先本地时间 2 小时
 pub const DB_FILE: &str = "blockchain.db";
-const VERSION: u8 = 0; // 假设版本号为 0  
+pub const WALLET_FILE: &str = "wallet.dat";
+pub const COOKIE_FILE: &str = ".cookie"; // 节点启动时写入 JSON-RPC 的认证信息
+// 恢复 HD 钱包时, 连续这么多个未使用的地址之后停止扫描
+pub const GAP_LIMIT: u32 = 20;
+pub const MAX_MEMPOOL_SIZE: usize = 1_000_000; // 内存池中交易序列化后的总字节数上限
+pub const MAX_BLOCK_SIZE: usize = 100_000; // 区块模板中交易序列化后的总字节数上限
 const ADDRESS_CHECKSUM_LEN: usize = 4; // 假设地址校验和的长度为 4
-
-
-
-
-
-
-
-
@@ -0,0 +1,355 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use sled::Tree;
+
+use crate::block::Block;
+use crate::block_chain::{BlockChain, ChainSwitch};
+use crate::coin_selection::Candidate;
+use crate::error::{Error, Result};
+use crate::miner::Miner;
+use crate::transactions::{Transaction, TxVerdict};
+use crate::UTXOset::{outpoint_key, UTXOSet};
+use crate::MAX_BLOCK_SIZE;
+
+// 待确认交易保存在数据库的这个 tree 中, 以交易 id 为键, 重启后重新加载
+const MEMPOOL_TREE: &str = "mempool";
+
+// 内存池中的一笔待确认交易, 以及它支付的手续费和序列化后的大小
+#[derive(Debug, Clone)]
+pub struct MempoolEntry {
+    pub tx: Transaction,
+    pub fee: i64,
+    pub size: usize,
+}
+
+impl MempoolEntry {
+    // 按手续费率 fee / size 比较, 交叉相乘避免浮点数
+    pub fn cmp_fee_rate(&self, other: &MempoolEntry) -> Ordering {
+        (self.fee as i128 * other.size as i128).cmp(&(other.fee as i128 * self.size as i128))
+    }
+}
+
+// 从内存池中按手续费率选出的一组交易, 不含 coinbase
+#[derive(Debug, Clone, Default)]
+pub struct BlockTemplate {
+    pub transactions: Vec<Transaction>,
+    pub fees: i64,
+}
+
+// 只接受花费主链上未花费输出的交易, 不接受依赖其他待确认交易的交易
+pub struct Mempool {
+    tree: Tree,
+    entries: HashMap<Vec<u8>, MempoolEntry>,
+    // 待确认交易花费的输出 (outpoint_key) -> 花费它的交易 id
+    spent: HashMap<Vec<u8>, Vec<u8>>,
+    size: usize,
+    pub max_size: usize,
+}
+
+impl Mempool {
+    // 加载上次保存的待确认交易, 按当前主链重新验证, 已经失效的直接丢弃
+    pub fn load(bc: &BlockChain, max_size: usize) -> Result<Mempool> {
+        let tree = bc.db.open_tree(MEMPOOL_TREE)?;
+        let mut stored = Vec::new();
+        for item in tree.iter() {
+            let (_key, value) = item?;
+            stored.push(Transaction::deserialize_transaction(&value)?);
+        }
+
+        let mut mempool = Mempool {
+            tree,
+            entries: HashMap::new(),
+            spent: HashMap::new(),
+            size: 0,
+            max_size,
+        };
+        for tx in stored {
+            let id = tx.id.clone();
+            mempool.readd(tx, bc)?;
+            if !mempool.entries.contains_key(&id) {
+                mempool.tree.remove(&id)?;
+            }
+        }
+        Ok(mempool)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // 所有待确认交易序列化后的总字节数
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn get(&self, tx_id: &[u8]) -> Option<&MempoolEntry> {
+        self.entries.get(tx_id)
+    }
+
+    // 按手续费率从高到低排列的待确认交易
+    pub fn entries(&self) -> Vec<&MempoolEntry> {
+        let mut entries: Vec<&MempoolEntry> = self.entries.values().collect();
+        entries.sort_by(|a, b| b.cmp_fee_rate(a));
+        entries
+    }
+
+    // 某个输出是否已经被待确认交易花费
+    pub fn spends(&self, tx_id: &[u8], vout: usize) -> bool {
+        self.spent.contains_key(&outpoint_key(tx_id, vout))
+    }
+
+    // 去掉已经被待确认交易花费的候选输出, 避免钱包构造出双花交易
+    pub fn filter_unspent(&self, candidates: Vec<Candidate>) -> Vec<Candidate> {
+        candidates
+            .into_iter()
+            .filter(|candidate| !self.spends(&candidate.tx_id, candidate.vout))
+            .collect()
+    }
+
+    // 验证交易并加入内存池, 总大小超过上限时逐出手续费率最低的交易
+    pub fn add(&mut self, tx: Transaction, bc: &BlockChain) -> Result<()> {
+        if tx.is_coinbase() {
+            return Err(Error::MempoolRejected("coinbase transactions are only valid in blocks".to_string()));
+        }
+        if self.entries.contains_key(&tx.id) {
+            return Err(Error::MempoolRejected(format!("transaction {} is already pending", hex::encode(&tx.id))));
+        }
+        if bc.find_transaction_height(&tx.id)?.is_some() {
+            return Err(Error::MempoolRejected(format!("transaction {} is already in the chain", hex::encode(&tx.id))));
+        }
+
+        let verdict = bc.verify_transaction(&tx)?;
+        if !verdict.is_valid() {
+            return Err(verdict.into());
+        }
+        // 双花检查: 输出在主链上必须未花费, 并且没有被其他待确认交易花费
+        let utxo_set = UTXOSet {
+            blockchain: bc.clone(),
+        };
+        for (in_id, vin) in tx.inputs.iter().enumerate() {
+            if utxo_set.get_entry(&vin.transcation_id, vin.vout)?.is_none() {
+                return Err(TxVerdict::AlreadySpent { input: in_id }.into());
+            }
+            if let Some(other) = self.spent.get(&outpoint_key(&vin.transcation_id, vin.vout)) {
+                return Err(Error::MempoolRejected(format!(
+                    "input {} conflicts with pending transaction {}",
+                    in_id,
+                    hex::encode(other)
+                )));
+            }
+        }
+
+        let entry = MempoolEntry {
+            fee: tx.fee(&bc.prev_transactions(&tx)?),
+            size: tx.serialize().len(),
+            tx,
+        };
+        let id = entry.tx.id.clone();
+        self.insert(entry)?;
+        self.evict()?;
+        if !self.entries.contains_key(&id) {
+            return Err(Error::MempoolRejected("mempool is full and the fee rate is too low".to_string()));
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, entry: MempoolEntry) -> Result<()> {
+        self.tree.insert(&entry.tx.id, entry.tx.serialize())?;
+        for vin in &entry.tx.inputs {
+            self.spent.insert(outpoint_key(&vin.transcation_id, vin.vout), entry.tx.id.clone());
+        }
+        self.size += entry.size;
+        self.entries.insert(entry.tx.id.clone(), entry);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, tx_id: &[u8]) -> Result<Option<MempoolEntry>> {
+        let entry = match self.entries.remove(tx_id) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        self.tree.remove(tx_id)?;
+        for vin in &entry.tx.inputs {
+            self.spent.remove(&outpoint_key(&vin.transcation_id, vin.vout));
+        }
+        self.size -= entry.size;
+        Ok(Some(entry))
+    }
+
+    fn evict(&mut self) -> Result<()> {
+        while self.size > self.max_size {
+            let lowest = match self.entries.values().min_by(|a, b| a.cmp_fee_rate(b)) {
+                Some(entry) => entry.tx.id.clone(),
+                None => break,
+            };
+            self.remove(&lowest)?;
+        }
+        Ok(())
+    }
+
+    // 重新加入之前接受过的交易, 在新的主链上不再有效时直接丢弃
+    fn readd(&mut self, tx: Transaction, bc: &BlockChain) -> Result<()> {
+        match self.add(tx, bc) {
+            Err(e) if e.is_storage() => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    // 按手续费率从高到低选出不超过 MAX_BLOCK_SIZE 的交易
+    pub fn block_template(&self) -> BlockTemplate {
+        let mut template = BlockTemplate::default();
+        let mut size = 0;
+        for entry in self.entries() {
+            if size + entry.size > MAX_BLOCK_SIZE {
+                continue;
+            }
+            size += entry.size;
+            template.fees += entry.fee;
+            template.transactions.push(entry.tx.clone());
+        }
+        template
+    }
+
+    // 接在当前 tip 之后的区块中的交易: 模板中的交易, 最后是支付给 address 的 coinbase
+    // 在当前主链上已经失效的交易不打包, 而不是让整个区块无效
+    pub fn block_candidate(&self, bc: &BlockChain, address: &str) -> Result<Vec<Transaction>> {
+        let template = self.block_template();
+        let mut txs = Vec::new();
+        let mut fees = 0;
+        for tx in template.transactions {
+            if Self::is_valid_on(&tx, bc)? {
+                fees += self.entries[&tx.id].fee;
+                txs.push(tx);
+            }
+        }
+        let height = bc.height()? + 1;
+        txs.push(Transaction::new_coinbase_transcation(address, "Reward", height, fees as i32, &bc.config)?);
+        Ok(txs)
+    }
+
+    // 交易在 bc 的主链上是否仍然有效: 验证通过, 并且花费的输出都未花费
+    fn is_valid_on(tx: &Transaction, bc: &BlockChain) -> Result<bool> {
+        if !bc.verify_transaction(tx)?.is_valid() {
+            return Ok(false);
+        }
+        let utxo_set = UTXOSet {
+            blockchain: bc.clone(),
+        };
+        for vin in &tx.inputs {
+            if utxo_set.get_entry(&vin.transcation_id, vin.vout)?.is_none() {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // 用 block_candidate 挖出新区块 (MineBlock 同时更新 UTXO 集), 之后移出已打包的交易
+    pub fn mine(&mut self, bc: &mut BlockChain, address: &str, miner: &Miner) -> Result<Block> {
+        let txs = self.block_candidate(bc, address)?;
+        let block = bc.MineBlock(txs, miner)?;
+        self.remove_block(&block)?;
+        Ok(block)
+    }
+
+    // 区块接入主链后, 删除其中已确认的交易以及与它们冲突的待确认交易
+    pub fn remove_block(&mut self, block: &Block) -> Result<()> {
+        for tx in &block.transactions {
+            self.remove(&tx.id)?;
+            if tx.is_coinbase() {
+                continue;
+            }
+            for vin in &tx.inputs {
+                if let Some(conflict) = self.spent.get(&outpoint_key(&vin.transcation_id, vin.vout)).cloned() {
+                    self.remove(&conflict)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // 按主链的变化更新内存池: 新接入区块中的交易移出, 被断开区块中的交易放回
+    // 发生重组时, 剩下的交易可能花费了被断开区块中的输出, 全部重新验证
+    // bc 需要已经指向新的 tip, 并且 UTXO 集已经更新
+    pub fn apply(&mut self, switch: &ChainSwitch, bc: &BlockChain) -> Result<()> {
+        for block in &switch.connected {
+            self.remove_block(block)?;
+        }
+        if !switch.disconnected.is_empty() {
+            let pending: Vec<Transaction> = self.entries.values().map(|entry| entry.tx.clone()).collect();
+            for tx in pending {
+                if !Self::is_valid_on(&tx, bc)? {
+                    self.remove(&tx.id)?;
+                }
+            }
+        }
+        for block in &switch.disconnected {
+            for tx in &block.transactions {
+                if !tx.is_coinbase() {
+                    self.readd(tx.clone(), bc)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block_chain::tests::TestChain;
+
+    // 重组断开了待确认交易花费的输出所在的区块, 这笔交易要被移出, 否则之后挖出的区块都无效
+    #[test]
+    fn reorg_evicts_pending_transactions_spending_disconnected_outputs() {
+        let mut chain = TestChain::new("mempool-reorg");
+        let genesis_tx = chain.bc.get_block(&chain.bc.tip).unwrap().unwrap().transactions[0].clone();
+        let fork = chain.mine(Vec::new()).hash;
+        let parent = chain.spend(&genesis_tx, 0, 1);
+        chain.mine(vec![parent.clone()]);
+
+        let mut mempool = Mempool::load(&chain.bc, usize::MAX).unwrap();
+        let child = chain.spend(&parent, 0, 1);
+        mempool.add(child.clone(), &chain.bc).unwrap();
+
+        // 不含 parent 的侧链超过当前主链
+        let side1 = chain.side_block(&fork, Vec::new());
+        assert!(chain.bc.add_block(&side1).unwrap().is_none());
+        let side2 = chain.side_block(&side1.hash, Vec::new());
+        let switch = chain.bc.add_block(&side2).unwrap().unwrap();
+        assert_eq!(switch.disconnected.len(), 1);
+        mempool.apply(&switch, &chain.bc).unwrap();
+
+        assert!(mempool.get(&child.id).is_none());
+        assert!(!mempool.spends(&parent.id, 0));
+        assert!(mempool.get(&parent.id).is_some());
+
+        let block = mempool.mine(&mut chain.bc, &chain.address, &Miner::default()).unwrap();
+        assert!(block.transactions.iter().any(|tx| tx.id == parent.id));
+        assert!(mempool.is_empty());
+    }
+
+    // 模板中已经失效的交易不打包, 区块中只剩仍然有效的交易
+    #[test]
+    fn block_candidate_skips_invalid_transactions() {
+        let mut chain = TestChain::new("mempool-candidate");
+        let genesis_tx = chain.bc.get_block(&chain.bc.tip).unwrap().unwrap().transactions[0].clone();
+        chain.mine(Vec::new());
+        let mut mempool = Mempool::load(&chain.bc, usize::MAX).unwrap();
+        let spend = chain.spend(&genesis_tx, 0, 1);
+        mempool.add(spend.clone(), &chain.bc).unwrap();
+
+        // 同一个输出被直接挖进区块, 绕过 apply, 内存池中的交易失效
+        let conflict = chain.spend(&genesis_tx, 0, 2);
+        chain.mine(vec![conflict]);
+        let txs = mempool.block_candidate(&chain.bc, &chain.address).unwrap();
+        assert_eq!(txs.len(), 1);
+        assert!(txs[0].is_coinbase());
+        assert_eq!(txs[0].outputs[0].value, chain.coinbase(0).outputs[0].value);
+        chain.bc.check_transactions(&txs).unwrap();
+    }
+}
@@ -18,12 +18,6 @@ impl MerkleTree {
     pub fn new(data: Vec<Vec<u8>>) -> MerkleTree {  
         let mut nodes: Vec<MerkleNode> = Vec::new();  
 
-        let mut data = data;  
-
-        if data.len() % 2 != 0 {  
-            data.push(data[data.len() - 1].clone());  
-        }  
-
         for datum in data {  
             let node = MerkleNode::new(None, None, datum);  
             nodes.push(node);  
@@ -33,13 +27,14 @@ impl MerkleTree {
         while nodes.len() > 1 {  
             let mut new_level: Vec<MerkleNode> = Vec::new();  
 
-            for i in (0..nodes.len()).step_by(2) {  
-                let left = Some(Box::new(nodes[i].clone()));  
-                let right = if i + 1 < nodes.len() {  
-                    Some(Box::new(nodes[i + 1].clone()))  
-                } else {  
-                    None  
-                };  
+            // Odd number of nodes on this level: duplicate the last one  
+            if !nodes.len().is_multiple_of(2) {  
+                nodes.push(nodes[nodes.len() - 1].clone());  
+            }  
+
+            for pair in nodes.chunks(2) {  
+                let left = Some(Box::new(pair[0].clone()));  
+                let right = Some(Box::new(pair[1].clone()));  
                 let node = MerkleNode::new(left, right, Vec::new());  
                 new_level.push(node);  
             }  
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::block::Block;
+use crate::error::{Error, Result};
+use crate::proof_of_work::{MiningResult, ProofOfWork};
+
+// 多线程挖矿: nonce 空间分给 threads 个线程, 可以从其他线程取消, 记录最近一次挖矿的算力
+#[derive(Debug)]
+pub struct Miner {
+    threads: usize,
+    cancel: AtomicBool,
+    // 最近一次挖矿的平均算力 (哈希次数 / 秒)
+    hash_rate: Mutex<f64>,
+}
+
+impl Default for Miner {
+    // 每个 CPU 核心一个线程
+    fn default() -> Self {
+        Self::new(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+}
+
+impl Miner {
+    pub fn new(threads: usize) -> Miner {
+        Miner {
+            threads: threads.max(1),
+            cancel: AtomicBool::new(false),
+            hash_rate: Mutex::new(0.0),
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    // 让正在进行 (或者下一次开始) 的 mine 尽快返回 MiningCancelled
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    pub fn hash_rate(&self) -> f64 {
+        *self.hash_rate.lock().expect("hash rate lock poisoned")
+    }
+
+    // 为区块找到满足难度的 nonce, 写入区块头和区块哈希
+    // nonce 用完时增加 coinbase 的 extra-nonce (没有 coinbase 时把时间戳往后推) 再继续搜索
+    // 被取消时返回 MiningCancelled, 取消只作用于这一次挖矿
+    pub fn mine(&self, block: &mut Block) -> Result<()> {
+        let started = Instant::now();
+        let mut hashes: u64 = 0;
+        let result = loop {
+            let (result, count) = ProofOfWork::new(&block.header).search(self.threads, &self.cancel);
+            hashes += count;
+            if result != MiningResult::Exhausted {
+                break result;
+            }
+            if !block.increment_extra_nonce() {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
+                block.header.timestamp = now.max(block.header.timestamp + 1);
+            }
+        };
+
+        let elapsed = started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            *self.hash_rate.lock().expect("hash rate lock poisoned") = hashes as f64 / elapsed;
+        }
+        match result {
+            MiningResult::Found { nonce, hash } => {
+                block.header.nonce = nonce;
+                block.hash = hash;
+                Ok(())
+            }
+            _ => {
+                self.cancel.store(false, Ordering::Relaxed);
+                Err(Error::MiningCancelled)
+            }
+        }
+    }
+}
+
+// 以 H/s, kH/s 或 MH/s 显示算力
+pub fn format_hash_rate(rate: f64) -> String {
+    if rate >= 1_000_000.0 {
+        format!("{:.2} MH/s", rate / 1_000_000.0)
+    } else if rate >= 1_000.0 {
+        format!("{:.2} kH/s", rate / 1_000.0)
+    } else {
+        format!("{:.0} H/s", rate)
+    }
+}
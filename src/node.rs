@@ -0,0 +1,617 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::block::Block;
+use crate::block_chain::BlockChain;
+use crate::block_header::BlockHeader;
+use crate::error::{Error, Result};
+use crate::mempool::Mempool;
+use crate::miner::{format_hash_rate, Miner};
+use crate::proof_of_work::ProofOfWork;
+use crate::protocol::{
+    read_message, write_message, InvKind, Message, HEADERS_FIRST_VERSION, MAX_HEADERS, MAX_INV, MIN_PROTOCOL_VERSION,
+    PROTOCOL_VERSION,
+};
+use crate::transactions::Transaction;
+
+const USER_AGENT: &str = concat!("/blockchain-rs:", env!("CARGO_PKG_VERSION"), "/");
+// 每个节点同时最多有多少个区块体请求未完成
+const MAX_BLOCKS_IN_FLIGHT: usize = 16;
+// 只请求待接入队列最前面的这些区块, 限制乱序到达后缓存在内存中的区块数
+const BLOCK_DOWNLOAD_WINDOW: usize = 1024;
+// 超过这个时间还没有收到的区块体改向其他节点请求
+const BLOCK_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+// 发送时持有区块链和节点表的锁, 不读取数据的节点最多让它们阻塞这么久
+const PEER_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 所有连接共享的区块链, 内存池和区块体下载进度
+struct NodeState {
+    blockchain: BlockChain,
+    mempool: Mempool,
+    download: Download,
+}
+
+// 区块头同步之后等待下载的区块体, 可以同时向多个节点请求, 到达后按高度顺序接入主链
+#[derive(Default)]
+struct Download {
+    // 还没有接入的区块, 按高度排列
+    pending: VecDeque<Vec<u8>>,
+    // 已发出 getdata 的区块 -> (请求的节点, 发出的时间)
+    requested: HashMap<Vec<u8>, (SocketAddr, Instant)>,
+    // 已经收到, 但前面的区块还没有到的区块
+    received: HashMap<Vec<u8>, Block>,
+}
+
+impl Download {
+    // 换成新的待下载列表, 保留仍在列表中的请求和已收到的区块
+    fn reset(&mut self, hashes: Vec<Vec<u8>>) {
+        let wanted: HashSet<&Vec<u8>> = hashes.iter().collect();
+        self.requested.retain(|hash, _| wanted.contains(hash));
+        self.received.retain(|hash, _| wanted.contains(hash));
+        self.pending = hashes.into();
+    }
+
+    fn wants(&self, hash: &[u8]) -> bool {
+        self.pending.iter().any(|pending| pending.as_slice() == hash)
+    }
+}
+
+// 已完成握手的连接
+struct Peer {
+    writer: PeerWriter,
+    version: u32,
+    // 对方已知拥有的最高高度: 握手时的高度, 之后按收到的区块和区块头更新
+    height: u64,
+    mempool_requested: bool,
+}
+
+// 一个连接在自己的线程中维护的状态
+#[derive(Default)]
+struct Session {
+    handshake_done: bool,
+    version: u32,
+    // 对方上一条区块 inv 的最后一个哈希, 收到它之后继续请求后面的区块 (只用于不支持区块头同步的节点)
+    last_inv_block: Option<Vec<u8>>,
+}
+
+type PeerWriter = Arc<Mutex<TcpStream>>;
+
+// 后台挖矿线程: 收到唤醒信号后不断把内存池打包成区块, 直到内存池为空
+// 挖矿期间不持有节点状态锁, 其他节点送来新区块时取消当前挖矿, 在新的 tip 上重新打包
+struct Mining {
+    // 出块奖励和手续费发给这个地址
+    address: String,
+    miner: Miner,
+    wake: Sender<()>,
+}
+
+// P2P 节点: 每个 TCP 连接一个线程
+// 新节点先向对方请求区块头, 验证后再从多个节点并行下载区块体, 按顺序接入主链; 之后通过 inv 转发区块和交易
+// 加锁顺序固定为 state -> peers -> 单个连接的写入端
+#[derive(Clone)]
+pub struct Node {
+    state: Arc<Mutex<NodeState>>,
+    peers: Arc<Mutex<HashMap<SocketAddr, Peer>>>,
+    magic: [u8; 4],
+    // 设置后, 内存池中有交易时在后台挖矿
+    mining: Option<Arc<Mining>>,
+}
+
+impl Node {
+    // miner 为奖励地址和挖矿使用的 Miner, 设置时启动后台挖矿线程
+    pub fn new(blockchain: BlockChain, mempool: Mempool, miner: Option<(String, Miner)>) -> Node {
+        let (wake, wakeups) = mpsc::channel();
+        let node = Node {
+            magic: blockchain.config.magic,
+            state: Arc::new(Mutex::new(NodeState {
+                blockchain,
+                mempool,
+                download: Download::default(),
+            })),
+            peers: Arc::new(Mutex::new(HashMap::new())),
+            mining: miner.map(|(address, miner)| Arc::new(Mining { address, miner, wake })),
+        };
+        if let Some(mining) = node.mining.clone() {
+            let miner = node.clone();
+            thread::spawn(move || {
+                while wakeups.recv().is_ok() {
+                    // 合并挖矿期间积累的唤醒信号
+                    while wakeups.try_recv().is_ok() {}
+                    miner.mine(&mining);
+                }
+            });
+        }
+        node
+    }
+
+    // 连接 seeds 中的节点后在 port 上接受连接, 一直运行到进程退出
+    pub fn run(&self, port: u16, seeds: &[String]) -> Result<()> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        println!("Node listening on port {}", port);
+
+        self.wake_miner();
+
+        for seed in seeds {
+            match TcpStream::connect(seed) {
+                Ok(stream) => self.spawn_peer(stream),
+                Err(e) => println!("Failed to connect to {}: {}", seed, e),
+            }
+        }
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => self.spawn_peer(stream),
+                Err(e) => println!("Failed to accept a connection: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> MutexGuard<'_, NodeState> {
+        self.state.lock().expect("node state lock poisoned")
+    }
+
+    fn peers(&self) -> MutexGuard<'_, HashMap<SocketAddr, Peer>> {
+        self.peers.lock().expect("peer list lock poisoned")
+    }
+
+    fn spawn_peer(&self, stream: TcpStream) {
+        let node = self.clone();
+        thread::spawn(move || {
+            let addr = match stream.peer_addr() {
+                Ok(addr) => addr,
+                Err(_) => return,
+            };
+            if let Err(e) = node.handle_peer(stream, addr) {
+                println!("Peer {} disconnected: {}", addr, e);
+            }
+            // 交给这个节点的区块体请求改向其他节点发出
+            let mut state = node.state();
+            node.peers().remove(&addr);
+            if let Err(e) = node.schedule_downloads(&mut state) {
+                println!("Failed to schedule block downloads: {}", e);
+            }
+        });
+    }
+
+    fn handle_peer(&self, stream: TcpStream, addr: SocketAddr) -> Result<()> {
+        stream.set_write_timeout(Some(PEER_WRITE_TIMEOUT))?;
+        let writer: PeerWriter = Arc::new(Mutex::new(stream.try_clone()?));
+        let mut reader = stream;
+        let height = self.state().blockchain.height()?;
+        self.send(&writer, &Message::Version {
+            version: PROTOCOL_VERSION,
+            height,
+            user_agent: USER_AGENT.to_string(),
+        })?;
+
+        let mut session = Session::default();
+        loop {
+            let message = read_message(&mut reader, &self.magic)?;
+            self.handle_message(message, addr, &writer, &mut session)?;
+        }
+    }
+
+    // 写入超时或失败后消息可能只发出了一部分, 关闭连接, 由它自己的线程清理
+    fn send(&self, writer: &PeerWriter, message: &Message) -> Result<()> {
+        let mut stream = writer.lock().expect("peer stream lock poisoned");
+        let result = write_message(&mut *stream, &self.magic, message);
+        if result.is_err() {
+            let _ = stream.shutdown(Shutdown::Both);
+        }
+        result
+    }
+
+    // 发给除 except 以外的所有已握手节点, 写入失败的连接由它自己的线程清理
+    fn broadcast(&self, except: Option<SocketAddr>, message: &Message) {
+        let peers = self.peers();
+        for (addr, peer) in peers.iter() {
+            if Some(*addr) == except {
+                continue;
+            }
+            if let Err(e) = self.send(&peer.writer, message) {
+                println!("Failed to send to {}: {}", addr, e);
+            }
+        }
+    }
+
+    fn peer_height(&self, addr: SocketAddr) -> u64 {
+        self.peers().get(&addr).map_or(0, |peer| peer.height)
+    }
+
+    fn raise_peer_height(&self, addr: SocketAddr, height: u64) {
+        if let Some(peer) = self.peers().get_mut(&addr) {
+            peer.height = peer.height.max(height);
+        }
+    }
+
+    // 区块同步完成后向还没有请求过的节点请求内存池, 这时待确认交易引用的输出都能在本地验证
+    fn request_mempools(&self) {
+        let mut peers = self.peers();
+        for (addr, peer) in peers.iter_mut() {
+            if peer.mempool_requested {
+                continue;
+            }
+            peer.mempool_requested = true;
+            if let Err(e) = self.send(&peer.writer, &Message::Mempool) {
+                println!("Failed to send to {}: {}", addr, e);
+            }
+        }
+    }
+
+    fn handle_message(&self, message: Message, addr: SocketAddr, writer: &PeerWriter, session: &mut Session) -> Result<()> {
+        match message {
+            Message::Version { version, height, user_agent } => {
+                if version < MIN_PROTOCOL_VERSION {
+                    return Err(Error::Protocol(format!("peer protocol version {} is too old", version)));
+                }
+                println!("Connected to {} ({}, protocol {}, height {})", addr, user_agent, version, height);
+                session.handshake_done = true;
+                session.version = version;
+                self.peers().insert(addr, Peer {
+                    writer: writer.clone(),
+                    version,
+                    height,
+                    mempool_requested: false,
+                });
+                self.send(writer, &Message::Verack)?;
+
+                let state = self.state();
+                let bc = &state.blockchain;
+                if version >= HEADERS_FIRST_VERSION {
+                    // 总是先请求区块头: 对方的高度相同时也可能在另一条分支上
+                    return self.send(writer, &Message::GetHeaders { locator: bc.header_locator()? });
+                }
+                // 本地还没有创世区块时, 对方即使只有创世区块也要同步
+                if bc.tip.is_empty() || height > bc.height()? {
+                    self.send(writer, &Message::GetBlocks { locator: bc.locator()? })?;
+                } else {
+                    self.request_mempools();
+                }
+                Ok(())
+            }
+            _ if !session.handshake_done => Err(Error::Protocol("message received before the version handshake".to_string())),
+            Message::Verack => Ok(()),
+            Message::GetBlocks { locator } => {
+                let hashes = self.state().blockchain.hashes_after(&locator, MAX_INV)?;
+                if !hashes.is_empty() {
+                    self.send(writer, &Message::Inv { kind: InvKind::Block, items: hashes })?;
+                }
+                Ok(())
+            }
+            Message::GetHeaders { locator } => {
+                let headers = self.state().blockchain.headers_after(&locator, MAX_HEADERS)?;
+                self.send(writer, &Message::Headers(headers))
+            }
+            Message::Headers(headers) => self.handle_headers(headers, addr, writer),
+            Message::Mempool => {
+                let pending: Vec<Vec<u8>> =
+                    self.state().mempool.entries().iter().take(MAX_INV).map(|entry| entry.tx.id.clone()).collect();
+                if !pending.is_empty() {
+                    self.send(writer, &Message::Inv { kind: InvKind::Tx, items: pending })?;
+                }
+                Ok(())
+            }
+            Message::Inv { kind: InvKind::Block, items } => {
+                let state = self.state();
+                let mut unknown = Vec::new();
+                for hash in &items {
+                    if state.blockchain.get_header_index(hash)?.is_none() {
+                        unknown.push(hash.clone());
+                    }
+                }
+                if session.version >= HEADERS_FIRST_VERSION {
+                    // 先取得新区块的区块头, 区块体随后按下载队列请求
+                    if !unknown.is_empty() {
+                        self.send(writer, &Message::GetHeaders { locator: state.blockchain.header_locator()? })?;
+                    }
+                    return Ok(());
+                }
+                session.last_inv_block = items.last().cloned();
+                if !unknown.is_empty() {
+                    self.send(writer, &Message::GetData { kind: InvKind::Block, items: unknown })?;
+                } else if items.len() == MAX_INV {
+                    // 这一批都已经有了, 继续请求后面的区块
+                    self.send(writer, &Message::GetBlocks { locator: state.blockchain.locator()? })?;
+                }
+                Ok(())
+            }
+            Message::Inv { kind: InvKind::Tx, items } => {
+                let state = self.state();
+                let unknown: Vec<Vec<u8>> = items.into_iter().filter(|id| state.mempool.get(id).is_none()).collect();
+                if !unknown.is_empty() {
+                    self.send(writer, &Message::GetData { kind: InvKind::Tx, items: unknown })?;
+                }
+                Ok(())
+            }
+            Message::GetData { kind: InvKind::Block, items } => {
+                let state = self.state();
+                for hash in items {
+                    if let Some(block) = state.blockchain.get_block(&hash)? {
+                        self.send(writer, &Message::Block(block))?;
+                    }
+                }
+                Ok(())
+            }
+            Message::GetData { kind: InvKind::Tx, items } => {
+                let state = self.state();
+                for id in items {
+                    if let Some(entry) = state.mempool.get(&id) {
+                        self.send(writer, &Message::Tx(entry.tx.clone()))?;
+                    }
+                }
+                Ok(())
+            }
+            Message::Block(block) => self.handle_block(block, addr, writer, session),
+            Message::Tx(tx) => self.handle_tx(tx, addr),
+        }
+    }
+
+    // 验证并保存对方发来的区块头, 然后更新区块体的下载队列; 无效的区块头会断开连接
+    fn handle_headers(&self, headers: Vec<BlockHeader>, addr: SocketAddr, writer: &PeerWriter) -> Result<()> {
+        if headers.len() > MAX_HEADERS {
+            return Err(Error::Protocol(format!("headers message with {} headers exceeds the limit", headers.len())));
+        }
+        let mut guard = self.state();
+        let state = &mut *guard;
+        let bc = &mut state.blockchain;
+        for header in &headers {
+            let hash = bc.add_header(header)?;
+            if let Some(index) = bc.get_header_index(&hash)? {
+                self.raise_peer_height(addr, index.height);
+            }
+        }
+        if !headers.is_empty() {
+            println!("Received {} headers from {} (peer height {})", headers.len(), addr, self.peer_height(addr));
+        }
+        if headers.len() == MAX_HEADERS {
+            // 对方可能还有更多区块头
+            self.send(writer, &Message::GetHeaders { locator: bc.header_locator()? })?;
+        }
+
+        state.download.reset(bc.missing_bodies()?);
+        self.schedule_downloads(state)?;
+        if headers.len() < MAX_HEADERS && state.download.pending.is_empty() {
+            self.request_mempools();
+        }
+        Ok(())
+    }
+
+    // 把下载窗口内还没有请求的区块体分配给拥有它们且未完成请求最少的节点
+    fn schedule_downloads(&self, state: &mut NodeState) -> Result<()> {
+        let download = &mut state.download;
+        if download.pending.is_empty() {
+            return Ok(());
+        }
+        let peers = self.peers();
+        let now = Instant::now();
+        download.requested.retain(|_, (addr, at)| peers.contains_key(addr) && now.duration_since(*at) < BLOCK_DOWNLOAD_TIMEOUT);
+
+        let mut in_flight: HashMap<SocketAddr, usize> = HashMap::new();
+        for (addr, _) in download.requested.values() {
+            *in_flight.entry(*addr).or_default() += 1;
+        }
+        let mut batches: HashMap<SocketAddr, Vec<Vec<u8>>> = HashMap::new();
+        for hash in download.pending.iter().take(BLOCK_DOWNLOAD_WINDOW) {
+            if download.requested.contains_key(hash) || download.received.contains_key(hash) {
+                continue;
+            }
+            let height = match state.blockchain.get_header_index(hash)? {
+                Some(index) => index.height,
+                None => continue,
+            };
+            let chosen = peers
+                .iter()
+                .filter(|(_, peer)| peer.version >= HEADERS_FIRST_VERSION && peer.height >= height)
+                .map(|(addr, _)| (*addr, in_flight.get(addr).copied().unwrap_or(0)))
+                .filter(|(_, count)| *count < MAX_BLOCKS_IN_FLIGHT)
+                .min_by_key(|(_, count)| *count);
+            if let Some((addr, _)) = chosen {
+                *in_flight.entry(addr).or_default() += 1;
+                batches.entry(addr).or_default().push(hash.clone());
+                download.requested.insert(hash.clone(), (addr, now));
+            }
+        }
+
+        for (addr, items) in batches {
+            if let Err(e) = self.send(&peers[&addr].writer, &Message::GetData { kind: InvKind::Block, items }) {
+                println!("Failed to send to {}: {}", addr, e);
+            }
+        }
+        Ok(())
+    }
+
+    // 下载队列中的区块: 先缓存, 再从队列头开始按顺序接入主链
+    fn handle_downloaded(&self, state: &mut NodeState, block: Block, addr: SocketAddr) -> Result<()> {
+        // 区块体必须与已验证的区块头一致, 否则是对方发来了错误的数据
+        // 先比较 bits, 再用区块中的区块头计算哈希
+        let header = state.blockchain.get_header(&block.hash)?;
+        if header.map(|header| header.bits) != Some(block.header.bits)
+            || ProofOfWork::new(&block.header).hash() != block.hash
+            || block.header.merkle_root != Block::hash_transactions(&block.transactions)
+        {
+            return Err(Error::InvalidBlock(format!(
+                "block {} does not match its header",
+                hex::encode(&block.hash)
+            )));
+        }
+        state.download.requested.remove(&block.hash);
+        state.download.received.insert(block.hash.clone(), block);
+
+        while let Some(block) = state.download.pending.front().and_then(|hash| state.download.received.remove(hash)) {
+            state.download.pending.pop_front();
+            if let Err(e) = self.connect_block(state, &block, Some(addr)) {
+                // 区块头有效但区块中的交易无效: add_block 已把它和它的后代标记为无效, best_header 不再指向它们
+                // 按新的 best_header 重新排队, 并断开发来它的节点
+                println!("Block {} is invalid: {}", hex::encode(&block.hash), e);
+                state.download.reset(state.blockchain.missing_bodies()?);
+                return Err(e);
+            }
+        }
+        if state.download.pending.is_empty() {
+            self.request_mempools();
+        } else {
+            self.schedule_downloads(state)?;
+        }
+        Ok(())
+    }
+
+    // 接入一个父区块已经保存的区块, 主链变化时 (add_block 已经检查了新分支的交易并更新了 UTXO 集)
+    // 更新内存池并通告给其他节点
+    // from 为 None 表示本地挖出的区块; 其他节点的区块改变了主链时取消正在进行的挖矿
+    fn connect_block(&self, state: &mut NodeState, block: &Block, from: Option<SocketAddr>) -> Result<()> {
+        let bc = &mut state.blockchain;
+        if let Some(switch) = bc.add_block(block)? {
+            state.mempool.apply(&switch, bc)?;
+            let height = bc.height()?;
+            println!("Added block {} at height {}", hex::encode(&block.hash), height);
+            if let Some(addr) = from {
+                self.raise_peer_height(addr, height);
+                if let Some(ref mining) = self.mining {
+                    mining.miner.cancel();
+                }
+            }
+            self.broadcast(from, &Message::Inv { kind: InvKind::Block, items: vec![block.hash.clone()] });
+        }
+        Ok(())
+    }
+
+    fn handle_block(&self, block: Block, addr: SocketAddr, writer: &PeerWriter, session: &mut Session) -> Result<()> {
+        let mut guard = self.state();
+        let state = &mut *guard;
+        if state.blockchain.get_index(&block.hash)?.is_some() {
+            return Ok(());
+        }
+        if state.download.wants(&block.hash) {
+            return self.handle_downloaded(state, block, addr);
+        }
+
+        let bc = &state.blockchain;
+        let is_genesis = block.header.prev_block_hash.is_empty();
+        if !is_genesis && bc.get_index(&block.header.prev_block_hash)?.is_none() {
+            // 缺少父区块, 从双方的分叉点重新请求
+            let request = if session.version >= HEADERS_FIRST_VERSION {
+                Message::GetHeaders { locator: bc.header_locator()? }
+            } else {
+                Message::GetBlocks { locator: bc.locator()? }
+            };
+            return self.send(writer, &request);
+        }
+        self.connect_block(state, &block, Some(addr))?;
+
+        // 收到对方 inv 的最后一个区块后, 还落后就继续请求, 否则同步完成, 请求它的内存池
+        if session.last_inv_block.as_ref() == Some(&block.hash) {
+            session.last_inv_block = None;
+            if self.peer_height(addr) > state.blockchain.height()? {
+                self.send(writer, &Message::GetBlocks { locator: state.blockchain.locator()? })?;
+            } else {
+                self.request_mempools();
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_tx(&self, tx: Transaction, addr: SocketAddr) -> Result<()> {
+        let mut guard = self.state();
+        let state = &mut *guard;
+        if state.mempool.get(&tx.id).is_some() {
+            return Ok(());
+        }
+        let id = tx.id.clone();
+        match self.accept_transaction(state, tx, Some(addr)) {
+            Ok(()) => Ok(()),
+            Err(e) if e.is_storage() => Err(e),
+            // 无效的交易只是不转发, 不断开连接
+            Err(e) => {
+                println!("Rejected transaction {}: {}", hex::encode(&id), e);
+                Ok(())
+            }
+        }
+    }
+
+    // 交易加入内存池后转发给 from 以外的节点, 设置了 miner 时唤醒挖矿线程
+    fn accept_transaction(&self, state: &mut NodeState, tx: Transaction, from: Option<SocketAddr>) -> Result<()> {
+        let id = tx.id.clone();
+        state.mempool.add(tx, &state.blockchain)?;
+        println!("Accepted transaction {}", hex::encode(&id));
+        self.broadcast(from, &Message::Inv { kind: InvKind::Tx, items: vec![id] });
+        self.wake_miner();
+        Ok(())
+    }
+
+    // 本地构造的交易 (例如来自 JSON-RPC) 加入内存池并通告给所有节点, 被拒绝时返回原因
+    pub fn submit_transaction(&self, tx: Transaction) -> Result<()> {
+        let mut state = self.state();
+        self.accept_transaction(&mut state, tx, None)
+    }
+
+    // 在持有节点状态锁的情况下读取区块链和内存池, 期间各连接的消息处理会等待
+    pub fn with_state<R>(&self, f: impl FnOnce(&BlockChain, &Mempool) -> Result<R>) -> Result<R> {
+        let state = self.state();
+        f(&state.blockchain, &state.mempool)
+    }
+
+    // 最近一次挖矿的算力 (哈希次数 / 秒), 没有设置 miner 时为 None
+    pub fn hash_rate(&self) -> Option<f64> {
+        self.mining.as_ref().map(|mining| mining.miner.hash_rate())
+    }
+
+    fn wake_miner(&self) {
+        if let Some(ref mining) = self.mining {
+            // 挖矿线程只在进程退出时结束
+            let _ = mining.wake.send(());
+        }
+    }
+
+    // 在后台线程中把内存池打包成新区块并通告给所有节点, 直到内存池为空; 失败时只打印错误
+    fn mine(&self, mining: &Mining) {
+        loop {
+            let mut block = {
+                let state = self.state();
+                if state.mempool.is_empty() {
+                    return;
+                }
+                let bc = &state.blockchain;
+                let candidate = state
+                    .mempool
+                    .block_candidate(bc, &mining.address)
+                    .and_then(|txs| bc.new_block(txs));
+                match candidate {
+                    Ok(block) => block,
+                    Err(e) => {
+                        println!("Mining failed: {}", e);
+                        return;
+                    }
+                }
+            };
+
+            match mining.miner.mine(&mut block) {
+                Ok(()) => {}
+                // 主链已经变化, 在新的 tip 上重新打包
+                Err(Error::MiningCancelled) => continue,
+                Err(e) => {
+                    println!("Mining failed: {}", e);
+                    return;
+                }
+            }
+
+            let mut state = self.state();
+            if state.blockchain.tip != block.header.prev_block_hash {
+                continue;
+            }
+            if let Err(e) = self.connect_block(&mut state, &block, None) {
+                println!("Mining failed: {}", e);
+                return;
+            }
+            println!(
+                "Mined block {} with {} transactions ({})",
+                hex::encode(&block.hash),
+                block.transactions.len() - 1,
+                format_hash_rate(mining.miner.hash_rate())
+            );
+        }
+    }
+}
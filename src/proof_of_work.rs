@@ -1,80 +1,145 @@
-use num_bigint::BigUint;  
-use num_traits::One;  
-use num_traits::Zero;  
-use hex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use num_bigint::BigUint;
+use num_traits::{One, Zero};
 use sha3::{Sha3_256, Digest};
-use bincode::{serialize, deserialize};
 
-use crate::block::Block;
-use crate::{TARGET_BITS, MAX_NONCE};
+use crate::block_header::BlockHeader;
+use crate::{MAX_NONCE, MAX_TARGET_BITS, RETARGET_INTERVAL, TARGET_BLOCK_TIME};
+
+
+// 每计算这么多次哈希检查一次是否需要停止
+const CANCEL_CHECK_INTERVAL: u64 = 1024;
+
+// 一次 nonce 搜索的结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MiningResult {
+    Found { nonce: u32, hash: Vec<u8> },
+    // 整个 nonce 范围都不满足难度, 需要修改区块头的其他字段 (时间戳等) 后重新搜索
+    Exhausted,
+    // 被调用方取消, 例如收到了新的 tip
+    Cancelled,
+}
 
+pub struct ProofOfWork<'a> {
+    header: &'a BlockHeader,
+    target: BigUint,
+}
 
-pub struct ProofOfWork<'a> {  
-    block: &'a Block,  
-    target: BigUint,  
-}  
+impl<'a> ProofOfWork<'a> {
+    pub fn new(header: &'a BlockHeader) -> ProofOfWork<'a> {
+        // 目标值为 2^(256 - bits); bits 超过 256 时没有哈希能满足, 目标值取 0
+        let target = match 256u32.checked_sub(header.bits) {
+            Some(shift) => BigUint::one() << shift,
+            None => BigUint::zero(),
+        };
+        ProofOfWork { header, target }
+    }
+    //数据合并: 只对区块头做哈希, 交易通过 merkle root 间接参与
+    pub fn prepare_data(&self, nonce: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&int_to_hex(self.header.version as u64));
+        data.extend_from_slice(&self.header.prev_block_hash);
+        data.extend_from_slice(&self.header.merkle_root);
+        data.extend_from_slice(&int_to_hex(self.header.timestamp));
+        data.extend_from_slice(&int_to_hex(self.header.bits as u64));
+        data.extend_from_slice(&int_to_hex(nonce as u64));
 
-impl<'a> ProofOfWork<'a> {  
-    pub fn new(block: &'a Block) -> ProofOfWork<'a> {  
-        // 创建目标值  
-        let mut target = BigUint::one(); // 初始化为 1  
-        target <<= (256 - TARGET_BITS); // 左移以设置目标  
-        ProofOfWork { block, target }  
+        data
     }
-    //数据合并
-    pub fn prepare_data(&self, nonce: u32) -> Vec<u8> {  
-        let mut data = Vec::new();  
-        data.extend_from_slice(&self.block.previous_block_hash);  
-        let serialized_transactions = self.block.serialize_transactions();
-        data.extend_from_slice(&serialized_transactions);  
-        data.extend_from_slice(&int_to_hex(self.block.timestamp.try_into().unwrap()));  
-        data.extend_from_slice(&int_to_hex(TARGET_BITS as i64));  
-        data.extend_from_slice(&int_to_hex(nonce as i64));  
-
-        data  
-    }    
-    
-    pub fn run(&self) -> (u32, Vec<u8>) {  
-        let mut hash_int = BigUint::default();  
-        let mut hash = [0u8; 32];  
-        let mut nonce = 0;  
-    // println!("Mining the block containing \"{}\"",&self.block.data);  
-
-        while nonce < MAX_NONCE {  
-            let data = self.prepare_data(nonce);
-            let mut hasher = Sha3_256::new();  
-            hasher.update(&data);  
-            hash = hasher.finalize().into();
-            hash_int = BigUint::from_bytes_be(&hash);  
-
-            if hash_int < self.target {  
-                let hash_hex = hex::encode(&hash); // 使用 hex crate 转换为字符串  
-                println!("Pow: Find hash: {} from nonce: {}", hash_hex, nonce);   
-                break;  
-            } else {  
-                nonce += 1;  
-            }  
-        }  
-        println!("\n\n");  
-        (nonce, hash.to_vec())
+
+    // 在 [0, MAX_NONCE) 中寻找满足难度的 nonce, threads 个线程交错地分摊 nonce 空间
+    // cancel 被设置或者某个线程找到结果后, 其他线程在下一次检查时退出
+    // 返回结果以及所有线程一共计算的哈希次数
+    pub fn search(&self, threads: usize, cancel: &AtomicBool) -> (MiningResult, u64) {
+        let threads = threads.max(1) as u32;
+        let stop = AtomicBool::new(false);
+        let hashes = AtomicU64::new(0);
+        let found: Mutex<Option<(u32, Vec<u8>)>> = Mutex::new(None);
+
+        thread::scope(|scope| {
+            for worker in 0..threads {
+                let (stop, hashes, found) = (&stop, &hashes, &found);
+                scope.spawn(move || {
+                    let mut count: u64 = 0;
+                    let mut nonce = worker;
+                    while nonce < MAX_NONCE {
+                        if count.is_multiple_of(CANCEL_CHECK_INTERVAL) && (stop.load(Ordering::Relaxed) || cancel.load(Ordering::Relaxed)) {
+                            break;
+                        }
+                        let hash = self.hash_with(nonce);
+                        count += 1;
+                        if BigUint::from_bytes_be(&hash) < self.target {
+                            let mut found = found.lock().expect("mining result lock poisoned");
+                            if found.is_none() {
+                                *found = Some((nonce, hash));
+                            }
+                            stop.store(true, Ordering::Relaxed);
+                            break;
+                        }
+                        nonce = match nonce.checked_add(threads) {
+                            Some(next) => next,
+                            None => break,
+                        };
+                    }
+                    hashes.fetch_add(count, Ordering::Relaxed);
+                });
+            }
+        });
+
+        let result = match found.into_inner().expect("mining result lock poisoned") {
+            Some((nonce, hash)) => MiningResult::Found { nonce, hash },
+            None if cancel.load(Ordering::Relaxed) => MiningResult::Cancelled,
+            None => MiningResult::Exhausted,
+        };
+        (result, hashes.into_inner())
     }
-    
-    pub fn validate(&self) -> bool {  
-        let mut hash_int = BigUint::default();
-        let data = self.prepare_data(self.block.nonce);   
+
+    fn hash_with(&self, nonce: u32) -> Vec<u8> {
         let mut hasher = Sha3_256::new();
-        hasher.update(&data);
+        hasher.update(self.prepare_data(nonce));
+        hasher.finalize().to_vec()
+    }
+
+    // 按区块头当前的 nonce 重新计算区块哈希
+    pub fn hash(&self) -> Vec<u8> {
+        self.hash_with(self.header.nonce)
+    }
+
+    // 检查区块头的 bits 是否等于链在该高度期望的难度, 以及哈希是否满足该难度
+    pub fn validate(&self, expected_bits: u32) -> bool {
+        if self.header.bits != expected_bits {
+            return false;
+        }
         // 计算哈希
-        let hash = hasher.finalize();   
+        let hash = self.hash();
         // 将哈希字节转换为 BigUint
-        hash_int = BigUint::from_bytes_be(&hash); 
-        
-        hash_int < self.target  
-    }    
-}  
+        let hash_int = BigUint::from_bytes_be(&hash);
+
+        hash_int < self.target
+    }
+}
+
+// 根据上一个调整周期的实际耗时计算新的 bits
+// actual_timespan 是周期内第一个和最后一个区块的时间戳之差, 只包含 RETARGET_INTERVAL - 1 个出块间隔
+// 出块过快 (耗时不到期望的一半) 难度加一位, 过慢 (超过期望的两倍) 难度减一位
+// 难度不低于 min_bits (链的初始难度)
+pub fn retarget(bits: u32, actual_timespan: u64, min_bits: u32) -> u32 {
+    let expected_timespan = (RETARGET_INTERVAL as u64 - 1) * TARGET_BLOCK_TIME;
 
-fn int_to_hex(value: i64) -> Vec<u8> {  
-    let hex_string = format!("{:x}", value);  
-    hex_string.into_bytes()  
-}  
+    let new_bits = if actual_timespan * 2 < expected_timespan {
+        bits + 1
+    } else if actual_timespan > expected_timespan * 2 {
+        bits.saturating_sub(1)
+    } else {
+        bits
+    };
+    new_bits.clamp(min_bits, MAX_TARGET_BITS)
+}
 
+fn int_to_hex(value: u64) -> Vec<u8> {
+    let hex_string = format!("{:x}", value);
+    hex_string.into_bytes()
+}
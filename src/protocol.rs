@@ -0,0 +1,81 @@
+use std::io::{Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::block::Block;
+use crate::block_header::BlockHeader;
+use crate::error::{Error, Result};
+use crate::transactions::Transaction;
+
+// 本节点使用的协议版本, 握手时发送; 低于 MIN_PROTOCOL_VERSION 的节点会被断开
+pub const PROTOCOL_VERSION: u32 = 2;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+// 从这个版本开始支持 getheaders / headers, 先同步区块头再下载区块体;
+// 更早的节点仍然通过 getblocks 逐批同步
+pub const HEADERS_FIRST_VERSION: u32 = 2;
+// 单条消息的最大长度, 防止对方用超长的长度字段耗尽内存
+pub const MAX_MESSAGE_SIZE: usize = 32 * 1024 * 1024;
+// 一条 inv 消息最多列出的条目数
+pub const MAX_INV: usize = 500;
+// 一条 headers 消息最多包含的区块头数, 收满时继续请求
+pub const MAX_HEADERS: usize = 2000;
+
+// inv 和 getdata 中条目的类型
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvKind {
+    Block,
+    Tx,
+}
+
+// 节点之间的消息, 用 bincode 编码, 沿用 Block 和 Transaction 已有的序列化
+#[derive(Serialize, Deserialize, Debug)]
+pub enum Message {
+    // 连接建立后双方各自发送, 带上协议版本和主链高度
+    Version {
+        version: u32,
+        height: u64,
+        user_agent: String,
+    },
+    // 确认收到对方的 Version
+    Verack,
+    // 通告自己拥有的区块或交易
+    Inv { kind: InvKind, items: Vec<Vec<u8>> },
+    // 请求 inv 中列出的区块或交易
+    GetData { kind: InvKind, items: Vec<Vec<u8>> },
+    // 请求定位器之后的主链区块, 对方以 inv 回复
+    GetBlocks { locator: Vec<Vec<u8>> },
+    // 请求对方内存池中的交易, 对方以 inv 回复; 同步完区块之后发送
+    Mempool,
+    Block(Block),
+    Tx(Transaction),
+    // 请求定位器之后的主链区块头, 对方以 headers 回复, 没有更多区块头时回复空列表
+    GetHeaders { locator: Vec<Vec<u8>> },
+    Headers(Vec<BlockHeader>),
+}
+
+// 帧格式: 4 字节网络标识, 4 字节大端的负载长度, 之后是 bincode 编码的 Message
+pub fn write_message(stream: &mut impl Write, magic: &[u8; 4], message: &Message) -> Result<()> {
+    let payload = bincode::serialize(message)?;
+    let mut frame = Vec::with_capacity(8 + payload.len());
+    frame.extend_from_slice(magic);
+    frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&payload);
+    stream.write_all(&frame)?;
+    stream.flush()?;
+    Ok(())
+}
+
+pub fn read_message(stream: &mut impl Read, magic: &[u8; 4]) -> Result<Message> {
+    let mut head = [0u8; 8];
+    stream.read_exact(&mut head)?;
+    if &head[..4] != magic {
+        return Err(Error::Protocol(format!("unexpected network magic {}", hex::encode(&head[..4]))));
+    }
+    let len = u32::from_be_bytes(head[4..].try_into().expect("4-byte length")) as usize;
+    if len > MAX_MESSAGE_SIZE {
+        return Err(Error::Protocol(format!("message of {} bytes exceeds the limit", len)));
+    }
+    let mut payload = vec![0u8; len];
+    stream.read_exact(&mut payload)?;
+    Ok(bincode::deserialize(&payload)?)
+}
@@ -0,0 +1,467 @@
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+use base64::Engine;
+use serde_json::{json, Value};
+
+use crate::block::Block;
+use crate::coin_selection::{Fee, Strategy};
+use crate::config::ChainConfig;
+use crate::error::{Error, Result};
+use crate::functions;
+use crate::node::Node;
+use crate::transactions::Transaction;
+use crate::wallet::Wallets;
+use crate::UTXOset::UTXOSet;
+
+// cookie 文件中的用户名, 密码在每次启动时随机生成
+const COOKIE_USER: &str = "__cookie__";
+// 请求行加上全部请求头, 以及请求体的长度上限
+const MAX_HEADER_SIZE: u64 = 8 * 1024;
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+// JSON-RPC 2.0 规定的错误码
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+// 方法本身执行失败 (余额不足, 交易被拒绝等), 原因在 message 中
+const APPLICATION_ERROR: i64 = -32000;
+
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+impl RpcError {
+    fn invalid_params(message: impl Into<String>) -> RpcError {
+        RpcError { code: INVALID_PARAMS, message: message.into() }
+    }
+}
+
+impl From<Error> for RpcError {
+    fn from(e: Error) -> Self {
+        RpcError { code: APPLICATION_ERROR, message: e.to_string() }
+    }
+}
+
+type RpcResult = std::result::Result<Value, RpcError>;
+
+// 按位置传入的参数
+struct Params<'a>(&'a [Value]);
+
+impl Params<'_> {
+    fn get(&self, index: usize) -> Option<&Value> {
+        self.0.get(index).filter(|value| !value.is_null())
+    }
+
+    fn str(&self, index: usize, name: &str) -> std::result::Result<&str, RpcError> {
+        match self.get(index) {
+            Some(value) => value.as_str().ok_or_else(|| RpcError::invalid_params(format!("{} must be a string", name))),
+            None => Err(RpcError::invalid_params(format!("missing parameter {}", name))),
+        }
+    }
+
+    fn opt_str(&self, index: usize, name: &str) -> std::result::Result<Option<&str>, RpcError> {
+        match self.get(index) {
+            Some(_) => self.str(index, name).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn hex(&self, index: usize, name: &str) -> std::result::Result<Vec<u8>, RpcError> {
+        hex::decode(self.str(index, name)?).map_err(|_| RpcError::invalid_params(format!("{} must be hex", name)))
+    }
+
+    fn opt_i32(&self, index: usize, name: &str, default: i32) -> std::result::Result<i32, RpcError> {
+        match self.get(index) {
+            Some(value) => value
+                .as_i64()
+                .and_then(|value| i32::try_from(value).ok())
+                .ok_or_else(|| RpcError::invalid_params(format!("{} must be an integer", name))),
+            None => Ok(default),
+        }
+    }
+
+    fn i32(&self, index: usize, name: &str) -> std::result::Result<i32, RpcError> {
+        match self.get(index) {
+            Some(_) => self.opt_i32(index, name, 0),
+            None => Err(RpcError::invalid_params(format!("missing parameter {}", name))),
+        }
+    }
+
+    fn opt_bool(&self, index: usize, name: &str, default: bool) -> std::result::Result<bool, RpcError> {
+        match self.get(index) {
+            Some(value) => value.as_bool().ok_or_else(|| RpcError::invalid_params(format!("{} must be a boolean", name))),
+            None => Ok(default),
+        }
+    }
+}
+
+struct HttpRequest {
+    method: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+// 节点内的本地 JSON-RPC 服务: HTTP POST 请求体为 JSON-RPC 2.0 请求, 使用 cookie 文件中的用户名和密码做 Basic 认证
+#[derive(Clone)]
+pub struct RpcServer {
+    node: Node,
+    wallets: Arc<Mutex<Wallets>>,
+    config: ChainConfig,
+    // Authorization 头中期望的 "用户名:密码"
+    credentials: String,
+}
+
+impl RpcServer {
+    // 生成新的随机密码并写入 cookie 文件, 只有能读取数据目录的本地用户可以调用
+    pub fn new(node: Node, wallets: Wallets, config: &ChainConfig) -> Result<RpcServer> {
+        let credentials = format!("{}:{}", COOKIE_USER, hex::encode(rand::random::<[u8; 32]>()));
+        write_cookie(&config.cookie_path(), &credentials)?;
+        Ok(RpcServer {
+            node,
+            wallets: Arc::new(Mutex::new(wallets)),
+            config: config.clone(),
+            credentials,
+        })
+    }
+
+    // 在本机地址上监听, 端口被占用时返回错误; 之后在后台线程中处理请求, 每个连接一个线程
+    pub fn spawn(&self, port: u16) -> Result<()> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("JSON-RPC server listening on 127.0.0.1:{}, cookie in {}", port, self.config.cookie_path().display());
+        let server = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let server = server.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = server.handle_connection(stream) {
+                                println!("JSON-RPC connection error: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => println!("Failed to accept a JSON-RPC connection: {}", e),
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn wallets(&self) -> MutexGuard<'_, Wallets> {
+        self.wallets.lock().expect("wallet lock poisoned")
+    }
+
+    // 每个连接只处理一个请求, 回复后关闭
+    fn handle_connection(&self, stream: TcpStream) -> Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let (status, body) = match read_request(&mut reader) {
+            Ok(request) if !self.authorized(&request) => ("401 Unauthorized", String::new()),
+            Ok(request) if request.method != "POST" => ("405 Method Not Allowed", String::new()),
+            Ok(request) => ("200 OK", self.handle_body(&request.body).to_string()),
+            Err(reason) => ("400 Bad Request", reason),
+        };
+        write_response(stream, status, &body)
+    }
+
+    fn authorized(&self, request: &HttpRequest) -> bool {
+        let decoded = request
+            .authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Basic "))
+            .and_then(|encoded| base64::engine::general_purpose::STANDARD.decode(encoded.trim()).ok());
+        match decoded {
+            Some(credentials) => ring::constant_time::verify_slices_are_equal(&credentials, self.credentials.as_bytes()).is_ok(),
+            None => false,
+        }
+    }
+
+    fn handle_body(&self, body: &[u8]) -> Value {
+        let request: Value = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(e) => return error_response(Value::Null, RpcError { code: PARSE_ERROR, message: e.to_string() }),
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let method = match request.get("method").and_then(Value::as_str) {
+            Some(method) => method,
+            None => {
+                let error = RpcError { code: INVALID_REQUEST, message: "missing method".to_string() };
+                return error_response(id, error);
+            }
+        };
+        let params = match request.get("params") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(params)) => params.clone(),
+            Some(_) => return error_response(id, RpcError::invalid_params("params must be an array")),
+        };
+
+        match self.dispatch(method, &Params(&params)) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => error_response(id, error),
+        }
+    }
+
+    fn dispatch(&self, method: &str, params: &Params) -> RpcResult {
+        match method {
+            "getblockcount" => self.get_block_count(),
+            "getblock" => self.get_block(params),
+            "getrawtransaction" => self.get_raw_transaction(params),
+            "sendrawtransaction" => self.send_raw_transaction(params),
+            "getbalance" => self.get_balance(params),
+            "listunspent" => self.list_unspent(params),
+            "getnewaddress" => self.get_new_address(),
+            "send" => self.send(params),
+            "getmininginfo" => self.get_mining_info(),
+            _ => Err(RpcError { code: METHOD_NOT_FOUND, message: format!("method {} not found", method) }),
+        }
+    }
+
+    fn get_block_count(&self) -> RpcResult {
+        let height = self.node.with_state(|bc, _| {
+            if bc.tip.is_empty() {
+                return Err(Error::NoBlockchain);
+            }
+            bc.height()
+        })?;
+        Ok(json!(height))
+    }
+
+    // 参数: 区块哈希, verbose (默认 true); verbose 为 false 时返回序列化区块的 hex
+    fn get_block(&self, params: &Params) -> RpcResult {
+        let hash = params.hex(0, "blockhash")?;
+        let verbose = params.opt_bool(1, "verbose", true)?;
+        Ok(self.node.with_state(|bc, _| {
+            let block = bc.get_block(&hash)?.ok_or_else(|| Error::BlockNotFound(hash.clone()))?;
+            if !verbose {
+                return Ok(json!(hex::encode(block.serialize())));
+            }
+            let height = bc.get_index(&hash)?.map(|index| index.height);
+            Ok(block_json(&block, height))
+        })?)
+    }
+
+    // 参数: 交易 id, verbose (默认 false); 先查内存池, 再查主链
+    fn get_raw_transaction(&self, params: &Params) -> RpcResult {
+        let id = params.hex(0, "txid")?;
+        let verbose = params.opt_bool(1, "verbose", false)?;
+        Ok(self.node.with_state(|bc, mempool| {
+            let (tx, height) = match mempool.get(&id) {
+                Some(entry) => (entry.tx.clone(), None),
+                None => (bc.find_transaction(&id)?, bc.find_transaction_height(&id)?),
+            };
+            if !verbose {
+                return Ok(json!(hex::encode(tx.serialize())));
+            }
+            let mut tx_json = tx_json(&tx, &self.config);
+            tx_json["blockheight"] = json!(height);
+            Ok(tx_json)
+        })?)
+    }
+
+    fn send_raw_transaction(&self, params: &Params) -> RpcResult {
+        let bytes = params.hex(0, "hexstring")?;
+        let tx = Transaction::deserialize_transaction(&bytes)?;
+        let id = hex::encode(&tx.id);
+        self.node.submit_transaction(tx)?;
+        Ok(json!(id))
+    }
+
+    // 给定地址时返回该地址的余额, 否则返回钱包中全部地址的余额之和
+    fn get_balance(&self, params: &Params) -> RpcResult {
+        let addresses = self.addresses(params.opt_str(0, "address")?)?;
+        let balance = self.node.with_state(|bc, _| {
+            let utxo_set = UTXOSet { blockchain: bc.clone() };
+            let mut balance: i64 = 0;
+            for address in &addresses {
+                balance += utxo_set.find_utxos(address)?.iter().map(|out| out.value as i64).sum::<i64>();
+            }
+            Ok(balance)
+        })?;
+        Ok(json!(balance))
+    }
+
+    // 未花费输出, 以及它们在下一个区块中能否花费 (coinbase 已成熟, 没有被待确认交易花费)
+    fn list_unspent(&self, params: &Params) -> RpcResult {
+        let addresses = self.addresses(params.opt_str(0, "address")?)?;
+        let unspent = self.node.with_state(|bc, mempool| {
+            let utxo_set = UTXOSet { blockchain: bc.clone() };
+            let tip_height = bc.height()?;
+            let mut unspent = Vec::new();
+            for address in &addresses {
+                let pub_key_hash = functions::address_to_pubkeyhash(address)?;
+                for (tx_id, vout, entry) in utxo_set.find_unspent(&pub_key_hash)? {
+                    unspent.push(json!({
+                        "txid": hex::encode(&tx_id),
+                        "vout": vout,
+                        "address": address,
+                        "amount": entry.output.value,
+                        "height": entry.height,
+                        "confirmations": tip_height - entry.height + 1,
+                        "coinbase": entry.is_coinbase,
+                        "spendable": entry.is_mature(tip_height + 1, bc.config.coinbase_maturity) && !mempool.spends(&tx_id, vout),
+                    }));
+                }
+            }
+            Ok(unspent)
+        })?;
+        Ok(json!(unspent))
+    }
+
+    fn get_new_address(&self) -> RpcResult {
+        let mut wallets = self.wallets();
+        let address = wallets.new_wallet()?;
+        wallets.save_to_file()?;
+        Ok(json!(address))
+    }
+
+    // 参数: from, to, amount, fee (默认 0), input_fee (默认 0), coin_selection (默认 bnb)
+    fn send(&self, params: &Params) -> RpcResult {
+        let from = params.str(0, "from")?;
+        let to = params.str(1, "to")?;
+        let amount = params.i32(2, "amount")?;
+        let fee = Fee {
+            base: params.opt_i32(3, "fee", 0)?,
+            per_input: params.opt_i32(4, "input_fee", 0)?,
+        };
+        let strategy: Strategy = params
+            .opt_str(5, "coin_selection")?
+            .unwrap_or("bnb")
+            .parse()
+            .map_err(RpcError::invalid_params)?;
+
+        let wallets = self.wallets();
+        let tx = self.node.with_state(|bc, mempool| {
+            let utxo_set = UTXOSet { blockchain: bc.clone() };
+            Transaction::new_utxo_transaction(from, to, amount, fee, strategy.selector().as_ref(), &wallets, &utxo_set, mempool)
+        })?;
+        let id = hex::encode(&tx.id);
+        self.node.submit_transaction(tx)?;
+        Ok(json!(id))
+    }
+
+    fn get_mining_info(&self) -> RpcResult {
+        Ok(self.node.with_state(|bc, mempool| {
+            let height = bc.height()?;
+            let bits = if bc.tip.is_empty() { self.config.target_bits } else { bc.expected_bits(&bc.tip)? };
+            Ok(json!({
+                "blocks": height,
+                "bits": bits,
+                "subsidy": functions::block_subsidy(&self.config, height + 1),
+                "pooledtx": mempool.len(),
+                "mempoolbytes": mempool.size(),
+                "network": self.config.network.name(),
+                "generate": self.node.hash_rate().is_some(),
+                "hashespersec": self.node.hash_rate().unwrap_or(0.0),
+            }))
+        })?)
+    }
+
+    // 参数中的地址, 没有给出时使用钱包中的全部地址
+    fn addresses(&self, address: Option<&str>) -> std::result::Result<Vec<String>, RpcError> {
+        match address {
+            Some(address) if functions::validate_address(address, &self.config) => Ok(vec![address.to_string()]),
+            Some(address) => Err(Error::InvalidAddress(address.to_string()).into()),
+            None => Ok(self.wallets().get_addresses()),
+        }
+    }
+}
+
+fn error_response(id: Value, error: RpcError) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": error.code, "message": error.message } })
+}
+
+fn block_json(block: &Block, height: Option<u64>) -> Value {
+    json!({
+        "hash": hex::encode(&block.hash),
+        "height": height,
+        "version": block.header.version,
+        "previousblockhash": hex::encode(&block.header.prev_block_hash),
+        "merkleroot": hex::encode(&block.header.merkle_root),
+        "time": block.header.timestamp,
+        "bits": block.header.bits,
+        "nonce": block.header.nonce,
+        "tx": block.transactions.iter().map(|tx| hex::encode(&tx.id)).collect::<Vec<_>>(),
+    })
+}
+
+fn tx_json(tx: &Transaction, config: &ChainConfig) -> Value {
+    let vin: Vec<Value> = if tx.is_coinbase() {
+        Vec::new()
+    } else {
+        tx.inputs.iter().map(|vin| json!({ "txid": hex::encode(&vin.transcation_id), "vout": vin.vout })).collect()
+    };
+    let vout: Vec<Value> = tx
+        .outputs
+        .iter()
+        .enumerate()
+        .map(|(n, out)| json!({ "n": n, "value": out.value, "address": functions::pubkeyhash_to_address(&out.PubKeyHash, config) }))
+        .collect();
+    json!({ "txid": hex::encode(&tx.id), "coinbase": tx.is_coinbase(), "vin": vin, "vout": vout })
+}
+
+// cookie 文件只允许所有者读写; mode 只在创建文件时生效, 已存在的 cookie 可能权限更宽, 先删除
+fn write_cookie(path: &Path, credentials: &str) -> Result<()> {
+    if path.exists() {
+        fs::remove_file(path)?;
+    }
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(credentials.as_bytes())?;
+    Ok(())
+}
+
+// 读取请求行, 请求头和 Content-Length 长度的请求体, 格式错误时返回原因
+fn read_request(reader: &mut BufReader<TcpStream>) -> std::result::Result<HttpRequest, String> {
+    let mut head = reader.by_ref().take(MAX_HEADER_SIZE);
+    let mut line = String::new();
+    head.read_line(&mut line).map_err(|e| e.to_string())?;
+    let method = line.split_whitespace().next().ok_or("empty request")?.to_string();
+
+    let mut content_length = 0;
+    let mut authorization = None;
+    loop {
+        line.clear();
+        if head.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Err("request headers are incomplete or too large".to_string());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line.split_once(':').ok_or("malformed header")?;
+        match name.trim().to_ascii_lowercase().as_str() {
+            "content-length" => content_length = value.trim().parse().map_err(|_| "invalid Content-Length")?,
+            "authorization" => authorization = Some(value.trim().to_string()),
+            _ => {}
+        }
+    }
+    if content_length > MAX_BODY_SIZE {
+        return Err("request body is too large".to_string());
+    }
+    let mut body = vec![0u8; content_length];
+    reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+    Ok(HttpRequest { method, authorization, body })
+}
+
+fn write_response(mut stream: TcpStream, status: &str, body: &str) -> Result<()> {
+    let mut response = format!("HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+    if status.starts_with("401") {
+        response.push_str("WWW-Authenticate: Basic realm=\"jsonrpc\"\r\n");
+    }
+    response.push_str("\r\n");
+    response.push_str(body);
+    stream.write_all(response.as_bytes())?;
+    stream.flush()?;
+    Ok(())
+}
@@ -1,19 +1,25 @@
-use crate::{block_chain::BlockChain, SUBSIDY};
+use crate::coin_selection::{CoinSelector, Fee};
+use crate::config::ChainConfig;
+use crate::error::{Error, Result};
 use crate::functions;
-use crate::wallet::Wallets;
+use crate::mempool::Mempool;
+use crate::wallet::{Wallet, Wallets};
 use crate::UTXOset::UTXOSet;
 
-use ring::{rand as ring_rand, signature::{self, EcdsaKeyPair, UnparsedPublicKey, Signature, ECDSA_P256_SHA256_ASN1}};
+use ring::{rand as ring_rand, signature::{UnparsedPublicKey, ECDSA_P256_SHA256_ASN1}};
 
-use serde::ser::SerializeTuple;
 use serde::{Deserialize, Serialize};  
 use sha3::{Sha3_256, Digest};
 use std::cmp::Ordering;  
-use rust_base58::{base58, ToBase58, FromBase58};
-use std::collections::HashMap; 
+use std::collections::{HashMap, HashSet}; 
+use std::fmt;
+use std::ops::Range;
 
 // use ring::signature::ECDSA_P256_SHA256_ASN1;
 
+// coinbase 输入数据中 extra-nonce 的位置: 高度 (8 字节) 之后的 8 字节  
+const EXTRA_NONCE_RANGE: Range<usize> = 8..16;
+
 
 
 #[derive(Debug, Deserialize, Serialize, Clone)]  
@@ -31,14 +37,14 @@ pub struct TXInput {
 	pub PubKey: Vec<u8>,
 }  
 
-#[derive(Debug, Deserialize, Serialize, Clone)]   
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]   
 pub struct TXOutput {  
     pub value: i32, 
     // pub ScriptPubKey: String, 
     pub PubKeyHash:Vec<u8>,
 }  
 
-#[derive(Serialize, Deserialize, Debug)]  
+#[derive(Serialize, Deserialize, Debug, PartialEq)]  
 pub struct TXOutputs {  
     pub outputs: Vec<TXOutput>,  
 }  
@@ -51,7 +57,7 @@ impl TXInput {
 
     pub fn uses_key(&self, pub_key_hash: &Vec<u8>) -> bool {  
         let locking_hash = functions::publicKey_to_hash(&self.PubKey);  
-        locking_hash.cmp(&pub_key_hash) == Ordering::Equal 
+        locking_hash.cmp(pub_key_hash) == Ordering::Equal 
 
         // pub_key_hash == locking_hash 
     }  
@@ -63,24 +69,33 @@ impl TXOutput {
     // }  
 
     // 将地址锁定到输出  
-    pub fn lock(&mut self, address: &String) {  
-        self.PubKeyHash = functions::address_to_pubkeyhash(&address)
+    pub fn lock(&mut self, address: &str) -> Result<()> {  
+        self.PubKeyHash = functions::address_to_pubkeyhash(address)?;
+        Ok(())
     }  
 
     // 检查输出是否被指定的公钥哈希锁定  
     pub fn is_locked_with_key(&self, pub_key_hash: &Vec<u8>) -> bool {  
-        self.PubKeyHash.cmp(&pub_key_hash) == Ordering::Equal  
+        self.PubKeyHash.cmp(pub_key_hash) == Ordering::Equal  
         // self.PubKeyHash == pub_key_hash
     }  
-    pub fn newTXOutput(value: i32, address: &String) -> TXOutput {
+    pub fn serialize(&self) -> Vec<u8> {  
+        bincode::serialize(self).expect("Serialization failed")  
+    }  
+
+    pub fn deserialize_output(data: &[u8]) -> Result<TXOutput> {  
+        Ok(bincode::deserialize(data)?)
+    }  
+
+    pub fn newTXOutput(value: i32, address: &str) -> Result<TXOutput> {
         let mut txo = TXOutput { 
             value, 
             PubKeyHash: Vec::new(),
         };  
         // println!("address {}", &address);
-        txo.lock(&address);
+        txo.lock(address)?;
         // println!("output hash{:?}", txo.PubKeyHash);
-        txo 
+        Ok(txo)
     } 
 }  
 
@@ -98,17 +113,37 @@ impl Transaction {
     } 
 
     pub fn serialize(&self) -> Vec<u8> {
-        let encoeded = bincode::serialize(self).expect("Error serializing transaction"); 
-        encoeded
+        bincode::serialize(self).expect("Error serializing transaction")
+    }
+
+    pub fn deserialize_transaction(data: &[u8]) -> Result<Transaction> {
+        Ok(bincode::deserialize(data)?)
     }
 
     pub fn set_id(&self) -> Vec<u8> {  
-        let id = self.set_hash();  
-        id
+        self.set_hash()
     }
 
-    pub fn new_coinbase_transcation(to: &String, data: &String) -> Transaction {  
-        let pubkey = data.as_bytes().to_vec();
+    // 按交易内容重新计算 id: 与创建交易时一样, id 和签名为空  
+    pub fn expected_id(&self) -> Vec<u8> {  
+        let mut tx = self.clone();  
+        tx.id = Vec::new();  
+        for vin in &mut tx.inputs {  
+            vin.Signature = Vec::new();  
+        }  
+        tx.set_id()
+    }
+
+    // coinbase 奖励为该高度的区块补贴加上区块内其他交易的手续费  
+    // 输入数据为区块高度 + extra-nonce + data, 高度保证不同区块的 coinbase 交易 id 不同  
+    // nonce 用完时矿工修改 extra-nonce, 得到新的 merkle root 继续搜索  
+    pub fn new_coinbase_transcation(to: &str, data: &str, height: u64, fees: i32, config: &ChainConfig) -> Result<Transaction> {  
+        if !functions::validate_address(to, config) {  
+            return Err(Error::InvalidAddress(to.to_string()));  
+        }  
+        let mut pubkey = height.to_be_bytes().to_vec();
+        pubkey.extend_from_slice(&0u64.to_be_bytes());
+        pubkey.extend_from_slice(data.as_bytes());
 
         let txin = TXInput {  
             transcation_id: Vec::new(),  
@@ -117,7 +152,7 @@ impl Transaction {
             PubKey: pubkey,  
         };  
     
-        let txout = TXOutput::newTXOutput(SUBSIDY, to);  
+        let txout = TXOutput::newTXOutput(functions::block_subsidy(config, height) + fees, to)?;  
     
         let mut tx = Transaction {  
             id: Vec::new(),  
@@ -127,60 +162,99 @@ impl Transaction {
         
         tx.id = tx.set_id(); 
         // println!("coinbaSE Gen tx{:?}", &tx.id);
-        tx  
+        Ok(tx)
     }
 
+    // coinbase 输入数据中的 extra-nonce, 不是 coinbase 或者数据不足 16 字节时为 None  
+    pub fn extra_nonce(&self) -> Option<u64> {  
+        if !self.is_coinbase() {  
+            return None;  
+        }  
+        let bytes = self.inputs[0].PubKey.get(EXTRA_NONCE_RANGE)?;  
+        Some(u64::from_be_bytes(bytes.try_into().ok()?))  
+    }  
+
+    // 修改 coinbase 的 extra-nonce 并重新计算交易 id, 返回是否修改成功  
+    pub fn set_extra_nonce(&mut self, extra_nonce: u64) -> bool {  
+        if self.extra_nonce().is_none() {  
+            return false;  
+        }  
+        self.inputs[0].PubKey[EXTRA_NONCE_RANGE].copy_from_slice(&extra_nonce.to_be_bytes());  
+        // 与 new_coinbase_transcation 一样在 id 为空时计算  
+        self.id = Vec::new();  
+        self.id = self.set_id();  
+        true  
+    }  
+
+    // 按 selector 的策略选择输入, 手续费为 fee.base 加上每个输入 fee.per_input  
+    // 已经被内存池中待确认交易花费的输出不会被选中  
+    #[allow(clippy::too_many_arguments)]
     pub fn new_utxo_transaction(
-            from_addr: &String, to_addr: &String, 
+            from_addr: &str, to_addr: &str, 
             amount: i32, 
-            bc: &BlockChain, 
+            fee: Fee, 
+            selector: &dyn CoinSelector, 
             cur_wallets: &Wallets, 
-            UTXOSet: &UTXOSet
-        ) -> Transaction {  
-        println!("A new transcation from: {}, to: {}, amount: {} \n", from_addr, to_addr, amount);  
+            utxo_set: &UTXOSet,
+            mempool: &Mempool
+        ) -> Result<Transaction> {  
+        let bc = &utxo_set.blockchain;  
+        println!("A new transcation from: {}, to: {}, amount: {}, fee: {} \n", from_addr, to_addr, amount, fee.base);  
+        for address in [from_addr, to_addr] {  
+            if !functions::validate_address(address, &bc.config) {  
+                return Err(Error::InvalidAddress(address.to_string()));  
+            }  
+        }  
         let mut inputs = Vec::new();  
         let mut outputs = Vec::new();  
 
-        let wallet = cur_wallets.get_wallet(&from_addr).expect("can't find wallet from the address");  
+        let wallet = cur_wallets.get_wallet(from_addr)
+            .ok_or_else(|| Error::WalletNotFound(from_addr.to_string()))?;  
         let pub_key_hash = functions::publicKey_to_hash(&wallet.public_key);  
-        let (acc, valid_outputs) = UTXOSet.find_spendable_outputs(&pub_key_hash, amount);  
-    
-        // println!("Accumulated: {} \n, Valid Outputs: {:?} \n ", acc, valid_outputs);  
-        if acc < amount {  
-            panic!("ERROR: Not enough funds");  
-        }  
+        // 输入需要同时覆盖转账金额和固定的手续费, 每个输入的费用由 selector 计入  
+        let target = amount as i64 + fee.base as i64;  
+        let candidates = mempool.filter_unspent(utxo_set.find_spendable(&pub_key_hash)?);  
+        let selected = selector.select(&candidates, target, fee.per_input).ok_or_else(|| {  
+            Error::InsufficientFunds {  
+                required: target,  
+                available: candidates.iter().map(|c| c.value as i64).sum(),  
+            }  
+        })?;  
+        let acc: i64 = selected.iter().map(|c| c.value as i64).sum();  
+        let required = amount as i64 + fee.total(selected.len());  
     
         // 构建输入列表  
-        for (txid, outs) in valid_outputs {  
-            for &out in &outs {  
-                let input = TXInput {  
-                    transcation_id: txid.clone(),  
-                    vout: out, 
-                    Signature: Vec::new(),
-                    PubKey: wallet.public_key.clone(),
-                };  
-                inputs.push(input);  
-            }  
+        for candidate in selected {  
+            let input = TXInput {  
+                transcation_id: candidate.tx_id,  
+                vout: candidate.vout, 
+                Signature: Vec::new(),
+                PubKey: wallet.public_key.clone(),
+            };  
+            inputs.push(input);  
         }  
     
         // 构建输出列表  
-        outputs.push(TXOutput::newTXOutput(amount, &to_addr));  
+        outputs.push(TXOutput::newTXOutput(amount, to_addr)?);  
         
-        if acc > amount {  
-            outputs.push(TXOutput::newTXOutput(acc - amount, &from_addr)); 
+        // 找零不包含手续费, 差额留给打包该交易的矿工  
+        // 找零不超过以后花费它的费用时不值得创建, 一并留给矿工  
+        let change = acc - required;  
+        if change > fee.per_input.max(0) as i64 {  
+            outputs.push(TXOutput::newTXOutput(change as i32, from_addr)?); 
         }  
     
         let mut tx = Transaction {  
             id: Vec::new(),  
-            inputs: inputs,  
-            outputs: outputs,  
+            inputs,  
+            outputs,  
         }; 
     
         tx.id = tx.set_id();  
         // println!(" ====================================sign pubkey {:?}", wallet.public_key);
         // println!(" ====================================from addr {:?}", from_addr);
-        bc.sign_transaction(&mut tx, &wallet.key_pair);
-        tx  
+        bc.sign_transaction(&mut tx, wallet)?;
+        Ok(tx)
     }  
     
     fn trimmed_copy(&self) -> Transaction {  
@@ -202,83 +276,160 @@ impl Transaction {
 
         Transaction {  
             id: self.id.clone(),  
-            inputs: inputs,  
-            outputs: outputs,  
+            inputs,  
+            outputs,  
         }  
     }  
 
-    pub fn sign(&mut self, key_pair: &EcdsaKeyPair, prev_txs: &HashMap<Vec<u8>, Transaction>) {  
+    // 钱包锁定时返回 Error::WalletLocked, 交易保持未签名  
+    pub fn sign(&mut self, wallet: &Wallet, prev_txs: &HashMap<Vec<u8>, Transaction>) -> Result<()> {  
         if self.is_coinbase() {  
-            return;  
+            return Ok(());  
         }  
+        let key_pair = wallet.key_pair()?;  
         let rng = ring_rand::SystemRandom::new(); 
         let mut tx_copy = self.trimmed_copy();  
         for (in_id, vin) in self.inputs.iter_mut().enumerate() {  
             // 获取前一个交易  
-            if let Some(prev_tx) = prev_txs.get(&vin.transcation_id) {  
+            let prev_tx = prev_txs.get(&vin.transcation_id)
+                .ok_or_else(|| Error::TxNotFound(vin.transcation_id.clone()))?;
+            let prev_out = prev_tx.outputs.get(vin.vout)
+                .ok_or(Error::InvalidTransaction(TxVerdict::MissingOutput { input: in_id }))?;
 
-                tx_copy.inputs[in_id].Signature = Vec::new();  
-                tx_copy.inputs[in_id].PubKey = prev_tx.outputs[vin.vout].PubKeyHash.clone();  
-                // tx_copy.id = tx_copy.set_id();  
+            tx_copy.inputs[in_id].Signature = Vec::new();  
+            tx_copy.inputs[in_id].PubKey = prev_out.PubKeyHash.clone();  
+            // tx_copy.id = tx_copy.set_id();  
 
-                // println!("cur tx_copy {:?}\n", tx_copy);
+            // println!("cur tx_copy {:?}\n", tx_copy);
 
-                let signature = key_pair.sign(&rng, &tx_copy.set_hash()).unwrap();  
+            let signature = key_pair.sign(&rng, &tx_copy.set_hash()).map_err(|_| Error::SigningFailed)?;  
 
-                vin.Signature = signature.as_ref().to_vec();  
-                // vin.PubKey = Vec::new (); // 清理公钥  
-            }  
+            vin.Signature = signature.as_ref().to_vec();  
+            // vin.PubKey = Vec::new (); // 清理公钥  
         }  
 
         // println!(" \n self.sign {:?} \n", self.inputs[0].Signature);
         // println!(" self.pubkey {:?} \n", self.inputs[0].PubKey);
+        Ok(())
     } 
 
-    pub fn verify(&self, prev_txs: &HashMap<Vec<u8>, Transaction>) -> bool {  
-        if self.is_coinbase() {  
-            return true;  
+    // 交易 id 必须与内容一致, 输出金额不能为负 (coinbase 也要检查)
+    // 逐个检查输入: 引用的输出必须存在, 公钥必须与锁定的 PubKeyHash 对应, 签名必须有效
+    // 最后检查输出总额不超过输入总额
+    pub fn verify(&self, prev_txs: &HashMap<Vec<u8>, Transaction>) -> TxVerdict {  
+        if self.id != self.expected_id() {  
+            return TxVerdict::IdMismatch;  
         }  
-    
-        for vin in &self.inputs {  
-            if !prev_txs.contains_key(&vin.transcation_id) {  
-                panic!("ERROR: Previous transaction is not correct");  
-            }  
+        if let Some(out_id) = self.outputs.iter().position(|out| out.value < 0) {  
+            return TxVerdict::NegativeOutput { output: out_id };  
         }  
-        // println!("prev_txs {:?} \n", prev_txs);
+        if self.is_coinbase() {  
+            return TxVerdict::Valid;  
+        }  
+
         let mut tx_copy = self.trimmed_copy();
-        // println!("tx_copy {:?} \n", tx_copy);  
-        // println!(" ver cur tx {:?} \n", self);
+        let mut input_total: i64 = 0;
+        let mut seen: HashSet<(&Vec<u8>, usize)> = HashSet::new();
     
         for (in_id, vin) in self.inputs.iter().enumerate() {  
-            // let prev_tx_key = hex::encode(&vin.transcation_id);  
+            if !seen.insert((&vin.transcation_id, vin.vout)) {
+                return TxVerdict::DuplicateInput { input: in_id };
+            }
             let prev_tx = match prev_txs.get(&vin.transcation_id) {  
                 Some(tx) => tx,  
-                None => {  
-                    return false; // 如果找不到前一交易，返回 false  
-                },  
-            };  // 安全地获取前一交易  
+                None => return TxVerdict::MissingPrevTx { input: in_id },  
+            };  
+            let prev_out = match prev_tx.outputs.get(vin.vout) {
+                Some(out) => out,
+                None => return TxVerdict::MissingOutput { input: in_id },
+            };
+            if !vin.uses_key(&prev_out.PubKeyHash) {
+                return TxVerdict::PubKeyMismatch { input: in_id };
+            }
     
+            // 与 sign 相同的方式构造被签名的数据
             tx_copy.inputs[in_id].Signature.clear();  
-            tx_copy.inputs[in_id].PubKey = prev_tx.outputs[vin.vout].PubKeyHash.clone();  
-            // tx_copy.id = tx_copy.set_hash(); // 不需要在这里更新哈希因为公钥会被清空  
-            // 清除公钥  
-            // tx_copy.inputs[in_id].PubKey.clear(); // 不清除公钥，保持其有效性  
-            // println!("ver copy {:?}", tx_copy);
+            tx_copy.inputs[in_id].PubKey = prev_out.PubKeyHash.clone();  
     
-            // 从公钥字节中提取公钥  
-            let pubkey_bytes = &vin.PubKey;  
-            // println!(" ver pubkey {:?}", pubkey_bytes);
-            let sig = &vin.Signature;  
-
-            let peer_public_key = UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, pubkey_bytes);
-            return peer_public_key.verify(tx_copy.set_hash().as_slice(), sig.as_ref()).is_ok();
+            let peer_public_key = UnparsedPublicKey::new(&ECDSA_P256_SHA256_ASN1, &vin.PubKey);
+            if peer_public_key.verify(tx_copy.set_hash().as_slice(), vin.Signature.as_ref()).is_err() {
+                return TxVerdict::InvalidSignature { input: in_id };
+            }
+            input_total += prev_out.value as i64;
         }  
-        true 
+
+        let output_total: i64 = self.outputs.iter().map(|out| out.value as i64).sum();
+        if output_total > input_total {
+            return TxVerdict::OutputsExceedInputs { input_total, output_total };
+        }
+        TxVerdict::Valid 
     }
 }  
 
+impl Transaction {
+    // 手续费 = 输入总额 - 输出总额, 需要在 verify 通过之后调用; coinbase 没有手续费
+    pub fn fee(&self, prev_txs: &HashMap<Vec<u8>, Transaction>) -> i64 {
+        if self.is_coinbase() {
+            return 0;
+        }
+        let input_total: i64 = self.inputs.iter()
+            .map(|vin| prev_txs[&vin.transcation_id].outputs[vin.vout].value as i64)
+            .sum();
+        let output_total: i64 = self.outputs.iter().map(|out| out.value as i64).sum();
+        input_total - output_total
+    }
+}
+
+// Transaction::verify 的结果, 失败时指出是哪一个输入 (或输出) 出了问题
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxVerdict {
+    Valid,
+    IdMismatch,
+    DuplicateInput { input: usize },
+    MissingPrevTx { input: usize },
+    MissingOutput { input: usize },
+    PubKeyMismatch { input: usize },
+    InvalidSignature { input: usize },
+    ImmatureCoinbase { input: usize },
+    AlreadySpent { input: usize },
+    NegativeOutput { output: usize },
+    OutputsExceedInputs { input_total: i64, output_total: i64 },
+}
+
+impl TxVerdict {
+    pub fn is_valid(&self) -> bool {
+        *self == TxVerdict::Valid
+    }
+}
+
+impl fmt::Display for TxVerdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxVerdict::Valid => write!(f, "valid"),
+            TxVerdict::IdMismatch => write!(f, "transaction id does not match its contents"),
+            TxVerdict::DuplicateInput { input } => write!(f, "input {} spends the same output as an earlier input", input),
+            TxVerdict::MissingPrevTx { input } => write!(f, "input {} references an unknown transaction", input),
+            TxVerdict::MissingOutput { input } => write!(f, "input {} references a missing output", input),
+            TxVerdict::PubKeyMismatch { input } => write!(f, "input {} public key does not match the locked hash", input),
+            TxVerdict::InvalidSignature { input } => write!(f, "input {} has an invalid signature", input),
+            TxVerdict::ImmatureCoinbase { input } => write!(f, "input {} spends a coinbase output that has not matured", input),
+            TxVerdict::AlreadySpent { input } => write!(f, "input {} spends an output that is already spent on the main chain", input),
+            TxVerdict::NegativeOutput { output } => write!(f, "output {} has a negative value", output),
+            TxVerdict::OutputsExceedInputs { input_total, output_total } => {
+                write!(f, "outputs ({}) exceed inputs ({})", output_total, input_total)
+            }
+        }
+    }
+}
+
 
 
+impl Default for TXOutputs {  
+    fn default() -> Self {  
+        Self::new()  
+    }  
+}  
+
 impl TXOutputs {  
     pub fn new() -> Self {
         TXOutputs {
@@ -289,10 +440,128 @@ impl TXOutputs {
     pub fn serialize(&self) -> Vec<u8> {  
         bincode::serialize(self).expect("Serialization failed")  
     }  
+    // deserialize_outputs deserializes TXOutputs  
+    pub fn deserialize_outputs(data: &[u8]) -> Result<TXOutputs> {  
+        Ok(bincode::deserialize(data)?)
+    }  
 }  
 
-// deserialize_outputs deserializes TXOutputs  
-fn deserialize_outputs(data: &[u8]) -> TXOutputs {  
-    bincode::deserialize(data).expect("Deserialization failed")  
-}  
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Network;
+
+    // 两个支付给同一个钱包的 coinbase 作为被花费的交易
+    struct Fixture {
+        wallet: Wallet,
+        address: String,
+        prev_txs: HashMap<Vec<u8>, Transaction>,
+        coinbases: Vec<Transaction>,
+    }
+
+    impl Fixture {
+        fn new() -> Fixture {
+            let config = ChainConfig::new(Network::Regtest, std::env::temp_dir());
+            let wallet = Wallet::new().unwrap();
+            let address = wallet.get_address(&config);
+            let coinbases: Vec<Transaction> = (1..=2)
+                .map(|height| Transaction::new_coinbase_transcation(&address, "Reward", height, 0, &config).unwrap())
+                .collect();
+            let prev_txs = coinbases.iter().map(|tx| (tx.id.clone(), tx.clone())).collect();
+            Fixture { wallet, address, prev_txs, coinbases }
+        }
+
+        // 花费 (coinbase 序号, vout) 列出的输出, 未签名
+        fn unsigned(&self, spends: &[(usize, usize)], value: i32) -> Transaction {
+            let mut tx = Transaction {
+                id: Vec::new(),
+                inputs: spends
+                    .iter()
+                    .map(|&(prev, vout)| TXInput {
+                        transcation_id: self.coinbases[prev].id.clone(),
+                        vout,
+                        Signature: Vec::new(),
+                        PubKey: self.wallet.public_key.clone(),
+                    })
+                    .collect(),
+                outputs: vec![TXOutput::newTXOutput(value, &self.address).unwrap()],
+            };
+            tx.id = tx.set_id();
+            tx
+        }
+
+        fn signed(&self, spends: &[(usize, usize)], value: i32) -> Transaction {
+            let mut tx = self.unsigned(spends, value);
+            tx.sign(&self.wallet, &self.prev_txs).unwrap();
+            tx
+        }
+
+        fn reward(&self) -> i32 {
+            self.coinbases[0].outputs[0].value
+        }
+    }
+
+    #[test]
+    fn signed_transaction_is_valid() {
+        let fixture = Fixture::new();
+        let tx = fixture.signed(&[(0, 0), (1, 0)], fixture.reward() * 2 - 1);
+        assert_eq!(tx.verify(&fixture.prev_txs), TxVerdict::Valid);
+        assert_eq!(tx.fee(&fixture.prev_txs), 1);
+    }
+
+    #[test]
+    fn changed_contents_do_not_match_the_id() {
+        let fixture = Fixture::new();
+        let mut tx = fixture.signed(&[(0, 0)], fixture.reward() - 1);
+        tx.outputs[0].value -= 1;
+        assert_eq!(tx.verify(&fixture.prev_txs), TxVerdict::IdMismatch);
+    }
+
+    #[test]
+    fn spending_an_output_twice_is_rejected() {
+        let fixture = Fixture::new();
+        let tx = fixture.signed(&[(0, 0), (0, 0)], fixture.reward());
+        assert_eq!(tx.verify(&fixture.prev_txs), TxVerdict::DuplicateInput { input: 1 });
+    }
+
+    #[test]
+    fn missing_output_is_rejected() {
+        let fixture = Fixture::new();
+        let tx = fixture.unsigned(&[(0, 1)], fixture.reward());
+        assert_eq!(tx.verify(&fixture.prev_txs), TxVerdict::MissingOutput { input: 0 });
+    }
+
+    #[test]
+    fn other_public_key_is_rejected() {
+        let fixture = Fixture::new();
+        let mut tx = fixture.unsigned(&[(0, 0), (1, 0)], fixture.reward());
+        tx.inputs[1].PubKey = Wallet::new().unwrap().public_key;
+        tx.id = tx.expected_id();
+        tx.sign(&fixture.wallet, &fixture.prev_txs).unwrap();
+        assert_eq!(tx.verify(&fixture.prev_txs), TxVerdict::PubKeyMismatch { input: 1 });
+    }
+
+    // 只篡改第二个输入的签名, 第一个输入仍然有效
+    #[test]
+    fn invalid_signature_on_a_later_input_is_rejected() {
+        let fixture = Fixture::new();
+        let mut tx = fixture.signed(&[(0, 0), (1, 0)], fixture.reward());
+        let last = tx.inputs[1].Signature.len() - 1;
+        tx.inputs[1].Signature[last] ^= 1;
+        assert_eq!(tx.verify(&fixture.prev_txs), TxVerdict::InvalidSignature { input: 1 });
+    }
+
+    #[test]
+    fn outputs_exceeding_inputs_are_rejected() {
+        let fixture = Fixture::new();
+        let tx = fixture.signed(&[(0, 0), (1, 0)], fixture.reward() * 2 + 1);
+        assert_eq!(
+            tx.verify(&fixture.prev_txs),
+            TxVerdict::OutputsExceedInputs {
+                input_total: fixture.reward() as i64 * 2,
+                output_total: fixture.reward() as i64 * 2 + 1,
+            }
+        );
+    }
+}
@@ -1,46 +1,151 @@
-use ring::{agreement::PublicKey, rand as ring_rand, signature::{self, EcdsaKeyPair, EcdsaSigningAlgorithm, KeyPair}};  
-use std::fs::{self, File};  
-use std::io::{self, Write, Read};  
-use std::path::Path;  
-use serde::{Serialize, Deserialize};
+use ring::{rand as ring_rand, signature::{EcdsaKeyPair, KeyPair}};  
+use ring::aead::{self, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::SecureRandom;
 // use serde_gob::{from_reader, to_writer}; 
 use sha3::{Sha3_256, Digest}; 
-use rust_base58::{base58, ToBase58, FromBase58};
-use std::{clone, collections::HashMap};  
+use serde::{Serialize, Deserialize};
+use std::collections::HashMap;  
+use std::fs;
+use std::io::Write;
+use std::num::NonZeroU32;
+use crate::error::{Error, Result};
 use crate::functions;
-use crate::DB_FILE;
+use crate::hd_wallet;
+use crate::config::ChainConfig;
 use ring::signature::ECDSA_P256_SHA256_ASN1_SIGNING;
-const VERSION: u8 = 0; // 假设版本号为 0  
-const ADDRESS_CHECKSUM_LEN: usize = 4; // 假设地址校验和的长度为 4
 
 // static ALGORITHM: &'static EcdsaSigningAlgorithm = &ECDSA_P256_SHA256_ASN1_SIGNING;
 pub struct Wallet {  
-    pub key_pair: EcdsaKeyPair,  
     pub public_key: Vec<u8>,  
+    // 私钥及其 PKCS#8 文档, 加密钱包锁定时为 None  
+    key_pair: Option<EcdsaKeyPair>,  
+    pkcs8: Option<Vec<u8>>,  
+    // 加密后的 PKCS#8 文档 (nonce 在前), 未加密的钱包为 None  
+    encrypted: Option<Vec<u8>>,  
 }  
 pub struct Wallets {  
     pub wallets: HashMap<String, Wallet>,  
+    // 地址的版本字节和钱包文件的位置都取决于所在的链  
+    pub config: ChainConfig,  
+    // 设置了口令时的密钥派生参数, 以及解锁后派生出的密钥  
+    kdf: Option<KdfParams>,  
+    key: Option<[u8; 32]>,  
+    // HD 钱包的助记词, 之后的新地址都从它派生  
+    hd: Option<HdSeed>,  
+}  
+
+// 助记词的明文 (加密钱包锁定时为 None) 和密文, 以及下一个要派生的地址序号  
+struct HdSeed {  
+    mnemonic: Option<String>,  
+    encrypted: Option<Vec<u8>>,  
+    next_index: u32,  
+}  
+
+// 从口令派生 AES-256-GCM 密钥所用的 PBKDF2 参数  
+#[derive(Serialize, Deserialize, Clone)]  
+struct KdfParams {  
+    salt: Vec<u8>,  
+    iterations: u32,  
+}  
+
+// 钱包文件中的一个钱包: 公钥明文保存, 便于锁定时列出地址  
+// secret 在未加密时是 PKCS#8 文档, 加密时是 nonce 后接密文  
+#[derive(Serialize, Deserialize)]  
+struct WalletEntry {  
+    public_key: Vec<u8>,  
+    secret: Vec<u8>,  
+}  
+
+// 与 WalletEntry 相同, secret 是助记词的明文或密文  
+#[derive(Serialize, Deserialize)]  
+struct HdEntry {  
+    secret: Vec<u8>,  
+    next_index: u32,  
+}  
+
+#[derive(Serialize, Deserialize)]  
+struct WalletFile {  
+    kdf: Option<KdfParams>,  
+    entries: Vec<WalletEntry>,  
+    hd: Option<HdEntry>,  
+}  
+
+// 没有 HD 种子的上一版钱包文件  
+#[derive(Deserialize)]  
+struct WalletFileV2 {  
+    kdf: Option<KdfParams>,  
+    entries: Vec<WalletEntry>,  
+}  
+
+// 最早的钱包文件: 只有未加密的 PKCS#8 文档  
+#[derive(Deserialize)]  
+struct LegacyWalletFile {  
+    keys: Vec<Vec<u8>>,  
 }  
 
+// 当前钱包文件格式的开头, 没有前缀的按最早的格式读取  
+const WALLET_FILE_MAGIC: &[u8] = b"WLT3";  
+const WALLET_FILE_MAGIC_V2: &[u8] = b"WLT2";  
+// 加密助记词时使用的附加数据  
+const MNEMONIC_AAD: &[u8] = b"mnemonic";  
+const KDF_ITERATIONS: u32 = 100_000;  
+const SALT_LEN: usize = 16;  
+
 impl Wallet {  
-    pub fn new() -> Self {  
-        let (public_key, key_pair) = Self::new_key_pair(); // 获取公钥和密钥对  
-        Wallet {  
-            public_key,  
-            key_pair,  
-        }  
+    pub fn new() -> Result<Self> {  
+        let rng = ring_rand::SystemRandom::new();  
+        let pkcs8_document = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)
+            .map_err(|_| Error::KeyGeneration)?;  
+        Self::from_pkcs8(pkcs8_document.as_ref())
     }  
 
-    fn new_key_pair() -> (Vec<u8>, EcdsaKeyPair) {  
-        let rng = ring_rand::SystemRandom::new();  
-        let pkcs8_document = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng).expect("Failed to generate Document");  
-        let pkcs8_bytes = pkcs8_document.as_ref().to_vec(); 
-        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &pkcs8_bytes).expect("Failed to generate key pair");  
+    // 从 PKCS#8 文档恢复钱包  
+    pub fn from_pkcs8(pkcs8: &[u8]) -> Result<Self> {  
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8)
+            .map_err(|_| Error::InvalidKey)?;  
         
         let public_key = key_pair.public_key(); // 引用公钥字节  
         let public_key_bytes: Vec<u8> = public_key.as_ref().to_vec(); 
 
-        (public_key_bytes, key_pair)  
+        Ok(Wallet {  
+            public_key: public_key_bytes,  
+            key_pair: Some(key_pair),  
+            pkcs8: Some(pkcs8.to_vec()),  
+            encrypted: None,  
+        })
+    }  
+
+    // 签名用的私钥, 钱包锁定时返回 Error::WalletLocked  
+    pub fn key_pair(&self) -> Result<&EcdsaKeyPair> {  
+        self.key_pair.as_ref().ok_or(Error::WalletLocked)  
+    }  
+
+    pub fn is_locked(&self) -> bool {  
+        self.key_pair.is_none()  
+    }  
+
+    fn encrypt(&mut self, key: &[u8; 32]) -> Result<()> {  
+        let pkcs8 = self.pkcs8.as_ref().ok_or(Error::WalletLocked)?;  
+        self.encrypted = Some(seal(key, &self.public_key, pkcs8)?);  
+        Ok(())  
+    }  
+
+    fn decrypt(&mut self, key: &[u8; 32]) -> Result<()> {  
+        let encrypted = self.encrypted.as_ref().ok_or(Error::InvalidKey)?;  
+        let pkcs8 = open(key, &self.public_key, encrypted)?;  
+        let unlocked = Wallet::from_pkcs8(&pkcs8)?;  
+        if unlocked.public_key != self.public_key {  
+            return Err(Error::InvalidKey);  
+        }  
+        self.key_pair = unlocked.key_pair;  
+        self.pkcs8 = unlocked.pkcs8;  
+        Ok(())  
+    }  
+
+    fn lock(&mut self) {  
+        self.key_pair = None;  
+        self.pkcs8 = None;  
     }  
     
     pub fn get_hash(&self) -> Vec<u8> {  
@@ -51,85 +156,407 @@ impl Wallet {
 
     
 
-    pub fn get_address(&self) -> String {  
+    pub fn get_address(&self, config: &ChainConfig) -> String {  
         let pub_key_hash = functions::publicKey_to_hash(&self.public_key);  
-        // println!("pub_key_hash: {:?} \n", pub_key_hash);
-
-        let mut versioned_payload = vec![VERSION];  
-        versioned_payload.extend(pub_key_hash);  
-        // println!("versioned_payload: {:?} \n", versioned_payload);
-        
-        let checksum = functions::checksum(&versioned_payload);  
-        // println!("checksum: {:?} \n ", checksum);
-        
-        let mut full_payload = versioned_payload;  
-        full_payload.extend(&checksum); // 添加校验和  
-        // println!("full_payload: {:?}\n ", full_payload);
-        let address = full_payload.to_base58();
-        // println!("address: {:?}\n ", address);
-        address  
+        functions::pubkeyhash_to_address(&pub_key_hash, config)  
     } 
 
     
 }  
 
+impl Default for Wallets {  
+    fn default() -> Self {  
+        Self::new(&ChainConfig::default())  
+    }  
+}  
+
 impl Wallets {  
-    pub fn new() -> Self {  
+    pub fn new(config: &ChainConfig) -> Self {  
         Wallets {  
             wallets: HashMap::new(),  
+            config: config.clone(),  
+            kdf: None,  
+            key: None,  
+            hd: None,  
         }  
     }  
     pub fn get_wallet(&self, address: &str) -> Option<&Wallet> {  
         self.wallets.get(address) 
     }  
 
-    pub fn add_wallet(&mut self, id: String) -> &Wallet {  
-        let wallet = Wallet::new();  
+    pub fn add_wallet(&mut self, id: String) -> Result<&Wallet> {  
+        let wallet = self.generate_wallet()?;  
         self.wallets.insert(id.clone(), wallet);  // 使用 id 的 clone()  
-    // 返回对钱包的引用  
-        self.wallets.get(&id).expect("Wallet not found") // 使用 ID 直接查找钱包 
+        // 返回对钱包的引用  
+        self.wallets.get(&id).ok_or(Error::WalletNotFound(id))
     }  
 
-    pub fn new_wallet(&mut self) -> String {
-        let wallet = Wallet::new();
-        let address = wallet.get_address();
+    pub fn new_wallet(&mut self) -> Result<String> {
+        let wallet = self.generate_wallet()?;
+        let address = wallet.get_address(&self.config);
         println!("Your Address is:  {}", &address);
         
         self.wallets.insert(address.clone(), wallet);
-        address
+        Ok(address)
     }
 
-        // 从文件加载钱包  
-    // pub fn load_from_file(&mut self, wallet_file: &str) -> io::Result<()> {  
-    //     if !Path::new(wallet_file).exists() {  
-    //         return Err(io::Error::new(io::ErrorKind::NotFound, "Wallet file does not exist"));  
-    //     }  
+    // HD 钱包按序号派生下一个密钥, 否则随机生成  
+    // 加密的钱包只能在解锁时添加新密钥, 新密钥用当前口令加密  
+    fn generate_wallet(&mut self) -> Result<Wallet> {  
+        match self.hd {  
+            Some(ref hd) => {  
+                let index = hd.next_index;  
+                let wallet = self.derive_wallet(index)?;  
+                if let Some(ref mut hd) = self.hd {  
+                    hd.next_index = index + 1;  
+                }  
+                Ok(wallet)  
+            }  
+            None => {  
+                let mut wallet = Wallet::new()?;  
+                if self.is_encrypted() {  
+                    let key = self.key.as_ref().ok_or(Error::WalletLocked)?;  
+                    wallet.encrypt(key)?;  
+                }  
+                Ok(wallet)  
+            }  
+        }  
+    }  
 
-    //     let mut file_content = Vec::new();  
-    //     let mut file = File::open(wallet_file)?;  
-    //     file.read_to_end(&mut file_content)?;  
+    // 派生路径上第 index 个钱包  
+    fn derive_wallet(&self, index: u32) -> Result<Wallet> {  
+        let hd = self.hd.as_ref().ok_or_else(|| Error::HdWallet("wallet has no HD seed".to_string()))?;  
+        let mnemonic = hd.mnemonic.as_ref().ok_or(Error::WalletLocked)?;  
+        let mut wallet = Wallet::from_pkcs8(&hd_wallet::derive_pkcs8(mnemonic, index)?)?;  
+        if self.is_encrypted() {  
+            let key = self.key.as_ref().ok_or(Error::WalletLocked)?;  
+            wallet.encrypt(key)?;  
+        }  
+        Ok(wallet)  
+    }  
+
+    pub fn is_hd(&self) -> bool {  
+        self.hd.is_some()  
+    }  
+
+    // 生成新的助记词, 返回给用户备份; 之后 new_wallet 都从它派生  
+    pub fn create_hd(&mut self) -> Result<String> {  
+        let mnemonic = hd_wallet::generate_mnemonic()?;  
+        self.import_mnemonic(&mnemonic)?;  
+        Ok(mnemonic)  
+    }  
 
-    //     let wallets: Wallets = from_reader(&file_content[..]).map_err(|e| {  
-    //         io::Error::new(io::ErrorKind::InvalidData, e)  
-    //     })?;  
+    // 导入助记词作为 HD 种子, 已有的随机密钥保持不变  
+    pub fn import_mnemonic(&mut self, phrase: &str) -> Result<()> {  
+        if self.is_hd() {  
+            return Err(Error::HdWallet("wallet already has an HD seed".to_string()));  
+        }  
+        let mnemonic = hd_wallet::normalize_mnemonic(phrase)?;  
+        let encrypted = match self.kdf {  
+            Some(_) => {  
+                let key = self.key.as_ref().ok_or(Error::WalletLocked)?;  
+                Some(seal(key, MNEMONIC_AAD, mnemonic.as_bytes())?)  
+            }  
+            None => None,  
+        };  
+        self.hd = Some(HdSeed {  
+            mnemonic: Some(mnemonic),  
+            encrypted,  
+            next_index: 0,  
+        });  
+        Ok(())  
+    }  
 
-    //     self.wallets = wallets.wallets;  
+    pub fn export_mnemonic(&self) -> Result<String> {  
+        let hd = self.hd.as_ref().ok_or_else(|| Error::HdWallet("wallet has no HD seed".to_string()))?;  
+        hd.mnemonic.clone().ok_or(Error::WalletLocked)  
+    }  
 
-    //     Ok(())  
-    // }  
+    // 沿派生路径扫描, 把 is_used 认为在链上出现过的地址加入钱包  
+    // 连续 gap_limit 个未使用的地址之后停止, 返回找到的已使用地址  
+    pub fn scan<F>(&mut self, gap_limit: u32, is_used: F) -> Result<Vec<String>>  
+    where  
+        F: Fn(&[u8]) -> bool,  
+    {  
+        let mut used = Vec::new();  
+        let mut unused_run = 0;  
+        let mut index = 0;  
+        while unused_run < gap_limit {  
+            let wallet = self.derive_wallet(index)?;  
+            if is_used(&functions::publicKey_to_hash(&wallet.public_key)) {  
+                let address = wallet.get_address(&self.config);  
+                self.wallets.insert(address.clone(), wallet);  
+                used.push(address);  
+                unused_run = 0;  
+                if let Some(ref mut hd) = self.hd {  
+                    hd.next_index = hd.next_index.max(index + 1);  
+                }  
+            } else {  
+                unused_run += 1;  
+            }  
+            index += 1;  
+        }  
+        Ok(used)  
+    }  
 
-    // // 保存钱包到文件  
-    // pub fn save_to_db(&self){ 
-    //     let db = sled::open(DB_FILE).expect("Failed to open database");  
-    //     db.insert("wallets", self.serialize()).expect("Failed to insert tip"); 
-    // }  
+    pub fn is_encrypted(&self) -> bool {  
+        self.kdf.is_some()  
+    }  
 
-    // pub fn serialize(&self) -> Vec<u8> {  
-    //     bincode::serialize(&self).unwrap()  
-    // }  
-    // pub fn deserialize_block(d: &[u8]) -> Block {  
-    //     let block: Block = bincode::deserialize(d).expect("Failed to deserialize block");  
-    //     block  
-    // } 
+    pub fn is_locked(&self) -> bool {  
+        self.is_encrypted() && self.key.is_none()  
+    }  
+
+    // 为未加密的钱包设置口令, 之后保存的钱包文件中不再有明文私钥  
+    pub fn encrypt(&mut self, passphrase: &str) -> Result<()> {  
+        if self.is_encrypted() {  
+            return Err(Error::WalletEncryption("wallet is already encrypted".to_string()));  
+        }  
+        let kdf = KdfParams::generate()?;  
+        let key = kdf.derive_key(passphrase);  
+        self.encrypt_with(&key)?;  
+        self.kdf = Some(kdf);  
+        self.key = Some(key);  
+        Ok(())  
+    }  
+
+    fn encrypt_with(&mut self, key: &[u8; 32]) -> Result<()> {  
+        for wallet in self.wallets.values_mut() {  
+            wallet.encrypt(key)?;  
+        }  
+        if let Some(ref mut hd) = self.hd {  
+            let mnemonic = hd.mnemonic.as_ref().ok_or(Error::WalletLocked)?;  
+            hd.encrypted = Some(seal(key, MNEMONIC_AAD, mnemonic.as_bytes())?);  
+        }  
+        Ok(())  
+    }  
+
+    // 丢弃内存中的私钥, 助记词和派生出的密钥  
+    pub fn lock(&mut self) {  
+        if !self.is_encrypted() {  
+            return;  
+        }  
+        for wallet in self.wallets.values_mut() {  
+            wallet.lock();  
+        }  
+        if let Some(ref mut hd) = self.hd {  
+            hd.mnemonic = None;  
+        }  
+        self.key = None;  
+    }  
+
+    // 口令错误时返回 Error::WrongPassphrase, 钱包保持锁定  
+    pub fn unlock(&mut self, passphrase: &str) -> Result<()> {  
+        let kdf = self.kdf.as_ref()
+            .ok_or_else(|| Error::WalletEncryption("wallet is not encrypted".to_string()))?;  
+        let key = kdf.derive_key(passphrase);  
+        if let Err(e) = self.decrypt_with(&key) {  
+            self.lock();  
+            return Err(e);  
+        }  
+        self.key = Some(key);  
+        Ok(())  
+    }  
+
+    fn decrypt_with(&mut self, key: &[u8; 32]) -> Result<()> {  
+        for wallet in self.wallets.values_mut() {  
+            wallet.decrypt(key)?;  
+        }  
+        if let Some(ref mut hd) = self.hd {  
+            let encrypted = hd.encrypted.as_ref().ok_or(Error::InvalidKey)?;  
+            let mnemonic = String::from_utf8(open(key, MNEMONIC_AAD, encrypted)?)
+                .map_err(|_| Error::InvalidKey)?;  
+            hd.mnemonic = Some(mnemonic);  
+        }  
+        Ok(())  
+    }  
+
+    // 用旧口令解锁后, 换新的盐重新派生密钥并加密全部私钥  
+    pub fn change_passphrase(&mut self, old_passphrase: &str, new_passphrase: &str) -> Result<()> {  
+        self.unlock(old_passphrase)?;  
+        let kdf = KdfParams::generate()?;  
+        let key = kdf.derive_key(new_passphrase);  
+        self.encrypt_with(&key)?;  
+        self.kdf = Some(kdf);  
+        self.key = Some(key);  
+        Ok(())  
+    }  
+
+    // 从钱包文件加载全部钱包, 文件不存在时返回空的 Wallets  
+    // 加密的钱包加载后处于锁定状态  
+    pub fn load_from_file(config: &ChainConfig) -> Result<Wallets> {  
+        let mut wallets = Wallets::new(config);  
+        let wallet_file = config.wallet_path();  
+        if !wallet_file.exists() {  
+            return Ok(wallets);  
+        }  
+
+        let content = fs::read(&wallet_file)?;  
+        let file: WalletFile = if let Some(data) = content.strip_prefix(WALLET_FILE_MAGIC) {  
+            bincode::deserialize(data)?  
+        } else if let Some(data) = content.strip_prefix(WALLET_FILE_MAGIC_V2) {  
+            let v2: WalletFileV2 = bincode::deserialize(data)?;  
+            WalletFile {  
+                kdf: v2.kdf,  
+                entries: v2.entries,  
+                hd: None,  
+            }  
+        } else {  
+            let legacy: LegacyWalletFile = bincode::deserialize(&content)?;  
+            WalletFile {  
+                kdf: None,  
+                entries: legacy.keys.into_iter()  
+                    .map(|pkcs8| WalletEntry { public_key: Vec::new(), secret: pkcs8 })  
+                    .collect(),  
+                hd: None,  
+            }  
+        };  
+
+        for entry in file.entries {  
+            let wallet = if file.kdf.is_some() {  
+                Wallet {  
+                    public_key: entry.public_key,  
+                    key_pair: None,  
+                    pkcs8: None,  
+                    encrypted: Some(entry.secret),  
+                }  
+            } else {  
+                Wallet::from_pkcs8(&entry.secret)?  
+            };  
+            wallets.wallets.insert(wallet.get_address(config), wallet);  
+        }  
+        wallets.hd = match file.hd {  
+            Some(entry) if file.kdf.is_some() => Some(HdSeed {  
+                mnemonic: None,  
+                encrypted: Some(entry.secret),  
+                next_index: entry.next_index,  
+            }),  
+            Some(entry) => Some(HdSeed {  
+                mnemonic: Some(String::from_utf8(entry.secret).map_err(|_| Error::InvalidKey)?),  
+                encrypted: None,  
+                next_index: entry.next_index,  
+            }),  
+            None => None,  
+        };  
+        wallets.kdf = file.kdf;  
+        Ok(wallets)  
+    }  
+
+    // 保存全部钱包到文件, 先写临时文件再重命名, 避免写到一半时留下损坏的钱包文件  
+    pub fn save_to_file(&self) -> Result<()> {  
+        let wallet_file = self.config.wallet_path();  
+        let mut entries = Vec::new();  
+        for address in self.get_addresses() {  
+            let wallet = &self.wallets[&address];  
+            let secret = if self.is_encrypted() {  
+                wallet.encrypted.clone()  
+            } else {  
+                wallet.pkcs8.clone()  
+            };  
+            entries.push(WalletEntry {  
+                public_key: wallet.public_key.clone(),  
+                secret: secret.ok_or(Error::WalletLocked)?,  
+            });  
+        }  
+        let hd = match self.hd {  
+            Some(ref hd) => {  
+                let secret = if self.is_encrypted() {  
+                    hd.encrypted.clone()  
+                } else {  
+                    hd.mnemonic.as_ref().map(|mnemonic| mnemonic.as_bytes().to_vec())  
+                };  
+                Some(HdEntry {  
+                    secret: secret.ok_or(Error::WalletLocked)?,  
+                    next_index: hd.next_index,  
+                })  
+            }  
+            None => None,  
+        };  
+        let file = WalletFile {  
+            kdf: self.kdf.clone(),  
+            entries,  
+            hd,  
+        };  
+
+        let mut content = WALLET_FILE_MAGIC.to_vec();  
+        content.extend(bincode::serialize(&file)?);  
+        let tmp_file = wallet_file.with_extension("tmp");  
+        // 临时文件只有所有者可读写, 与 rpc 的 cookie 文件相同; 已存在的临时文件可能权限更宽, 先删除  
+        if tmp_file.exists() {  
+            fs::remove_file(&tmp_file)?;  
+        }  
+        let mut options = fs::OpenOptions::new();  
+        options.write(true).create_new(true);  
+        #[cfg(unix)]  
+        {  
+            use std::os::unix::fs::OpenOptionsExt;  
+            options.mode(0o600);  
+        }  
+        options.open(&tmp_file)?.write_all(&content)?;  
+        fs::rename(&tmp_file, &wallet_file)?;  
+        Ok(())  
+    }  
+
+    pub fn get_addresses(&self) -> Vec<String> {  
+        let mut addresses: Vec<String> = self.wallets.keys().cloned().collect();  
+        addresses.sort();  
+        addresses  
+    }  
+}
+
+impl KdfParams {  
+    fn generate() -> Result<Self> {  
+        let rng = ring_rand::SystemRandom::new();  
+        let mut salt = vec![0u8; SALT_LEN];  
+        rng.fill(&mut salt).map_err(|_| Error::KeyGeneration)?;  
+        Ok(KdfParams {  
+            salt,  
+            iterations: KDF_ITERATIONS,  
+        })  
+    }  
+
+    fn derive_key(&self, passphrase: &str) -> [u8; 32] {  
+        let mut key = [0u8; 32];  
+        let iterations = NonZeroU32::new(self.iterations.max(1)).expect("iterations is non-zero");  
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, &self.salt, passphrase.as_bytes(), &mut key);  
+        key  
+    }  
+}  
+
+// AES-256-GCM 加密, 公钥作为附加数据, 防止密文被挪到别的钱包条目下  
+fn seal(key: &[u8; 32], public_key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {  
+    let rng = ring_rand::SystemRandom::new();  
+    let mut nonce_bytes = [0u8; aead::NONCE_LEN];  
+    rng.fill(&mut nonce_bytes).map_err(|_| Error::KeyGeneration)?;  
+
+    let sealing_key = aead_key(key)?;  
+    let mut in_out = plaintext.to_vec();  
+    sealing_key
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce_bytes), Aad::from(public_key), &mut in_out)
+        .map_err(|_| Error::WalletEncryption("failed to encrypt key".to_string()))?;  
+
+    let mut sealed = nonce_bytes.to_vec();  
+    sealed.extend(in_out);  
+    Ok(sealed)  
+}  
+
+fn open(key: &[u8; 32], public_key: &[u8], sealed: &[u8]) -> Result<Vec<u8>> {  
+    if sealed.len() < aead::NONCE_LEN {  
+        return Err(Error::InvalidKey);  
+    }  
+    let (nonce_bytes, ciphertext) = sealed.split_at(aead::NONCE_LEN);  
+    let nonce = Nonce::try_assume_unique_for_key(nonce_bytes).map_err(|_| Error::InvalidKey)?;  
+
+    let opening_key = aead_key(key)?;  
+    let mut in_out = ciphertext.to_vec();  
+    let plaintext = opening_key
+        .open_in_place(nonce, Aad::from(public_key), &mut in_out)
+        .map_err(|_| Error::WrongPassphrase)?;  
+    Ok(plaintext.to_vec())  
+}  
 
-}
\ No newline at end of file
+fn aead_key(key: &[u8; 32]) -> Result<LessSafeKey> {  
+    let unbound = UnboundKey::new(&aead::AES_256_GCM, key)
+        .map_err(|_| Error::WalletEncryption("invalid encryption key".to_string()))?;  
+    Ok(LessSafeKey::new(unbound))  
+}